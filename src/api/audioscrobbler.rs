@@ -1,9 +1,26 @@
-use axum::Json;
+use std::collections::{BTreeMap, HashMap};
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use log::debug;
+use md5::{Digest, Md5};
+use serde_json::{json, Map, Value};
+use subtle::ConstantTimeEq;
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 
+use sea_orm::DatabaseConnection;
 use crate::api::ScrobbleAPI;
+use crate::server::AppState;
+use crate::database;
+use crate::database::errors::MalojaError;
+use crate::entity::album::AlbumWrite;
+use crate::entity::api_key::ApiKeyRead;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
 
 pub const API: ScrobbleAPI = ScrobbleAPI {
     prefix: "/audioscrobbler/2.0",
@@ -12,18 +29,428 @@ pub const API: ScrobbleAPI = ScrobbleAPI {
     register: register_routes,
 };
 
-fn register_routes(mut router: OpenApiRouter) -> OpenApiRouter {
+/// Maximum amount of scrobbles in one track.scrobble request, as defined by the specification
+const MAX_SCROBBLES_PER_REQUEST: usize = 50;
+/// Parameters that describe a single scrobble in track.scrobble
+const SCROBBLE_PARAMS: [&str; 7] = ["artist", "track", "timestamp", "album", "albumArtist", "duration", "mbid"];
+
+fn register_routes(mut router: OpenApiRouter<AppState>) -> OpenApiRouter<AppState> {
     router = router.routes(routes!(mainendpoint, mainendpoint_post));
     router
 }
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(mainendpoint),
+    paths(mainendpoint, mainendpoint_post),
     info(title = "Audioscrobbler API", version = "2.0")
 )]
 pub struct ApiDoc;
 
+
+/// Error codes as defined in the <a href='https://www.last.fm/api/errorcodes'>specification</a>
+#[derive(Debug, Clone, Copy)]
+enum ErrorCode {
+    InvalidMethod = 3,
+    AuthenticationFailed = 4,
+    InvalidParameters = 6,
+    InvalidSessionKey = 9,
+    InvalidApiKey = 10,
+    OperationFailed = 8,
+    InvalidSignature = 13,
+}
+
+/// Codes of ignoredMessage in scrobble responses. The specification has no code for a missing or
+/// malformed timestamp, so those are reported like a timestamp that is too old
+#[derive(Debug, Clone, Copy)]
+enum IgnoredCode {
+    NotIgnored = 0,
+    TrackIgnored = 2,
+    TimestampTooOld = 3,
+}
+
+/// A single scrobble of a batch that could not be accepted, without failing the others
+struct IgnoredScrobble {
+    code: IgnoredCode,
+    message: String,
+}
+
+struct AudioscrobblerError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl AudioscrobblerError {
+    fn new(code: ErrorCode, message: &str) -> Self {
+        AudioscrobblerError { code, message: message.to_string() }
+    }
+    fn status(&self) -> StatusCode {
+        match self.code {
+            ErrorCode::InvalidMethod | ErrorCode::InvalidParameters => StatusCode::BAD_REQUEST,
+            ErrorCode::AuthenticationFailed | ErrorCode::InvalidSessionKey | ErrorCode::InvalidApiKey | ErrorCode::InvalidSignature => StatusCode::FORBIDDEN,
+            ErrorCode::OperationFailed => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl From<MalojaError> for AudioscrobblerError {
+    fn from(e: MalojaError) -> Self {
+        match e {
+            MalojaError::ParseError { message } => AudioscrobblerError::new(ErrorCode::InvalidParameters, &message),
+            e => AudioscrobblerError::new(ErrorCode::OperationFailed, &e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Xml,
+    Json,
+}
+
+/// Minimal element tree, since Last.fm's JSON responses are simply a conversion of their XML responses
+struct Element {
+    name: &'static str,
+    attributes: Vec<(&'static str, String)>,
+    content: Content,
+}
+
+enum Content {
+    Text(String),
+    Children(Vec<Element>),
+}
+
+impl Element {
+    fn text(name: &'static str, text: &str) -> Self {
+        Element { name, attributes: vec![], content: Content::Text(text.to_string()) }
+    }
+    fn corrected(name: &'static str, text: &str) -> Self {
+        Element { name, attributes: vec![("corrected", "0".to_string())], content: Content::Text(text.to_string()) }
+    }
+    fn parent(name: &'static str, children: Vec<Element>) -> Self {
+        Element { name, attributes: vec![], content: Content::Children(children) }
+    }
+
+    fn to_xml(&self) -> String {
+        let mut attributes = String::new();
+        for (key, value) in &self.attributes {
+            attributes.push_str(&format!(" {}=\"{}\"", key, escape_xml(value)));
+        }
+        let content = match &self.content {
+            Content::Text(text) => escape_xml(text),
+            Content::Children(children) => children.iter().map(|c| c.to_xml()).collect(),
+        };
+        format!("<{}{}>{}</{}>", self.name, attributes, content, self.name)
+    }
+
+    fn to_json(&self) -> Value {
+        match &self.content {
+            Content::Text(text) if self.attributes.is_empty() => Value::String(text.clone()),
+            Content::Text(text) => {
+                let mut map: Map<String, Value> = self.attributes.iter()
+                    .map(|(key, value)| (key.to_string(), Value::String(value.clone())))
+                    .collect();
+                map.insert("#text".to_string(), Value::String(text.clone()));
+                Value::Object(map)
+            }
+            Content::Children(children) => {
+                let mut map = Map::new();
+                for child in children {
+                    // repeated elements become arrays, single ones stay objects
+                    match map.get_mut(child.name) {
+                        Some(Value::Array(existing)) => existing.push(child.to_json()),
+                        Some(existing) => *existing = Value::Array(vec![existing.take(), child.to_json()]),
+                        None => { map.insert(child.name.to_string(), child.to_json()); }
+                    }
+                }
+                if !self.attributes.is_empty() {
+                    let attributes: Map<String, Value> = self.attributes.iter()
+                        .map(|(key, value)| (key.to_string(), Value::String(value.clone())))
+                        .collect();
+                    map.insert("@attr".to_string(), Value::Object(attributes));
+                }
+                Value::Object(map)
+            }
+        }
+    }
+}
+
+fn escape_xml(input: &str) -> String {
+    input.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn render(format: Format, status: StatusCode, result: Result<Element, AudioscrobblerError>) -> Response {
+    let (status, body, content_type) = match (format, result) {
+        (Format::Json, Ok(element)) => {
+            (status, json!({ element.name: element.to_json() }).to_string(), "application/json")
+        }
+        (Format::Json, Err(e)) => {
+            (e.status(), json!({ "error": e.code as u8, "message": e.message }).to_string(), "application/json")
+        }
+        (Format::Xml, Ok(element)) => {
+            (status, format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<lfm status=\"ok\">{}</lfm>", element.to_xml()), "application/xml")
+        }
+        (Format::Xml, Err(e)) => {
+            (e.status(), format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<lfm status=\"failed\"><error code=\"{}\">{}</error></lfm>", e.code as u8, escape_xml(&e.message)), "application/xml")
+        }
+    };
+    (status, [(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+/// Signature as defined in the specification: all parameters except format and callback, ordered by name,
+/// concatenated as name and value, followed by the secret, and then hashed
+pub(crate) fn signature(params: &HashMap<String, String>, secret: &str) -> String {
+    let sorted: BTreeMap<&String, &String> = params.iter()
+        .filter(|(key, _)| !["format", "callback", "api_sig"].contains(&key.as_str()))
+        .collect();
+    let mut hasher = Md5::new();
+    for (key, value) in sorted {
+        hasher.update(key.as_bytes());
+        hasher.update(value.as_bytes());
+    }
+    hasher.update(secret.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+fn required<'a>(params: &'a HashMap<String, String>, key: &str) -> Result<&'a str, AudioscrobblerError> {
+    params.get(key)
+        .map(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+        .ok_or(AudioscrobblerError::new(ErrorCode::InvalidParameters, &format!("Missing parameter {}", key)))
+}
+
+/// Makes sure the request comes from a known key and is signed with that key's secret
+async fn authenticate_signed(params: &HashMap<String, String>, db: &DatabaseConnection) -> Result<ApiKeyRead, AudioscrobblerError> {
+    let api_key = required(params, "api_key")
+        .map_err(|_| AudioscrobblerError::new(ErrorCode::InvalidApiKey, "Invalid API key"))?;
+    let api_key = database::repository::validate_api_key(api_key, db).await
+        .map_err(|_| AudioscrobblerError::new(ErrorCode::InvalidApiKey, "Invalid API key"))?;
+    let api_sig = required(params, "api_sig")
+        .map_err(|_| AudioscrobblerError::new(ErrorCode::InvalidSignature, "Invalid method signature supplied"))?;
+    if !api_sig.eq_ignore_ascii_case(&signature(params, &api_key.secret)) {
+        return Err(AudioscrobblerError::new(ErrorCode::InvalidSignature, "Invalid method signature supplied"));
+    }
+    Ok(api_key)
+}
+
+/// Write methods additionally need a session that was created with the same key
+async fn authenticate_session(params: &HashMap<String, String>, db: &DatabaseConnection) -> Result<ApiKeyRead, AudioscrobblerError> {
+    let api_key = authenticate_signed(params, db).await?;
+    let session_key = required(params, "sk")
+        .map_err(|_| AudioscrobblerError::new(ErrorCode::InvalidSessionKey, "Invalid session key"))?;
+    match database::repository::validate_session(session_key, db).await {
+        Ok(session_api_key) if session_api_key.id == api_key.id => Ok(api_key),
+        _ => Err(AudioscrobblerError::new(ErrorCode::InvalidSessionKey, "Invalid session key")),
+    }
+}
+
+/// Gets a parameter for one specific scrobble. Single scrobbles may also be submitted without index
+fn indexed<'a>(params: &'a HashMap<String, String>, key: &str, index: usize) -> Option<&'a str> {
+    params.get(&format!("{}[{}]", key, index))
+        .or(if index == 0 { params.get(key) } else { None })
+        .map(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+}
+
+/// The parameters of one scrobble, without index. This is what we keep as raw scrobble
+fn scrobble_params(params: &HashMap<String, String>, index: usize) -> HashMap<String, String> {
+    SCROBBLE_PARAMS.iter()
+        .filter_map(|key| indexed(params, key, index).map(|value| (key.to_string(), value.to_string())))
+        .collect()
+}
+
+/// Parses a stored raw scrobble, which only contains the parameters of that scrobble
+pub fn parse_raw(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let params: HashMap<String, String> = serde_json::from_value(payload.clone())?;
+    match parse_scrobble(&params, 0) {
+        Some(result) => result.map_err(|e| MalojaError::ParseError { message: e.message }),
+        None => Err(MalojaError::ParseError { message: "Raw scrobble has no artist".to_string() }),
+    }
+}
+
+/// Number of scrobbles in the request, going by the highest index of any indexed parameter
+fn submitted_count(params: &HashMap<String, String>) -> usize {
+    params.keys()
+        .filter_map(|key| key.strip_suffix(']')?.split_once('[')?.1.parse::<usize>().ok())
+        .map(|index| index.saturating_add(1))
+        .max()
+        .unwrap_or(0)
+}
+
+fn parse_scrobble(params: &HashMap<String, String>, index: usize) -> Option<Result<ScrobbleWrite, IgnoredScrobble>> {
+    // no artist means there is no scrobble at this index
+    let artist = indexed(params, "artist", index)?;
+    let Some(title) = indexed(params, "track", index) else {
+        return Some(Err(IgnoredScrobble { code: IgnoredCode::TrackIgnored, message: format!("Missing track for scrobble {}", index) }));
+    };
+    let Some(timestamp) = indexed(params, "timestamp", index).and_then(|t| t.parse::<i64>().ok()) else {
+        return Some(Err(IgnoredScrobble { code: IgnoredCode::TimestampTooOld, message: format!("Missing or invalid timestamp for scrobble {}", index) }));
+    };
+
+    let artists = vec![ArtistWrite {
+        id: None,
+        name: Some(artist.to_string()),
+        mbid: None,
+        spotify_id: None,
+    }];
+    let album = indexed(params, "album", index).map(|album_title| AlbumWrite {
+        id: None,
+        album_title: Some(album_title.to_string()),
+        album_artists: Some(match indexed(params, "albumArtist", index) {
+            Some(album_artist) => vec![ArtistWrite {
+                id: None,
+                name: Some(album_artist.to_string()),
+                mbid: None,
+                spotify_id: None,
+            }],
+            None => artists.clone(),
+        }),
+        mbid: None,
+        spotify_id: None,
+    });
+
+    Some(Ok(ScrobbleWrite {
+        timestamp,
+        track: TrackWrite {
+            id: None,
+            title: Some(title.to_string()),
+            primary_artists: Some(artists),
+            secondary_artists: None,
+            track_length: indexed(params, "duration", index).and_then(|d| d.parse().ok()),
+            album,
+            mbid: indexed(params, "mbid", index).map(|m| m.to_string()),
+            spotify_id: None,
+        },
+        origin: None,
+        listen_duration: None,
+        raw_scrobble: Some(RawScrobble::new(
+            ScrobbleSource::Audioscrobbler,
+            serde_json::to_value(scrobble_params(params, index)).expect("Parameters are valid json"),
+        )),
+    }))
+}
+
+/// Echo of the submitted track info, used in both scrobble and now playing responses
+fn track_info_elements(params: &HashMap<String, String>, index: usize) -> Vec<Element> {
+    vec![
+        Element::corrected("track", indexed(params, "track", index).unwrap_or_default()),
+        Element::corrected("artist", indexed(params, "artist", index).unwrap_or_default()),
+        Element::corrected("album", indexed(params, "album", index).unwrap_or_default()),
+        Element::corrected("albumArtist", indexed(params, "albumArtist", index).unwrap_or_default()),
+    ]
+}
+
+fn ignored_message(code: IgnoredCode, message: &str) -> Element {
+    Element { name: "ignoredMessage", attributes: vec![("code", (code as u8).to_string())], content: Content::Text(message.to_string()) }
+}
+
+/// Like the original Maloja, clients log in with the API key as password. The older token form
+/// is md5(username + md5(password)), so it can be checked against the key the same way
+fn verify_password(params: &HashMap<String, String>, username: &str, api_key: &ApiKeyRead) -> Result<(), AudioscrobblerError> {
+    let valid = if let Ok(password) = required(params, "password") {
+        password.as_bytes().ct_eq(api_key.key.as_bytes())
+    } else if let Ok(token) = required(params, "authToken") {
+        let key_hash = format!("{:x}", Md5::digest(api_key.key.as_bytes()));
+        let expected = format!("{:x}", Md5::digest(format!("{}{}", username, key_hash).as_bytes()));
+        token.to_ascii_lowercase().as_bytes().ct_eq(expected.as_bytes())
+    } else {
+        return Err(AudioscrobblerError::new(ErrorCode::InvalidParameters, "Missing parameter password"));
+    };
+    if !bool::from(valid) {
+        return Err(AudioscrobblerError::new(ErrorCode::AuthenticationFailed, "Invalid username or password"));
+    }
+    Ok(())
+}
+
+async fn get_mobile_session(params: &HashMap<String, String>, db: &DatabaseConnection) -> Result<Element, AudioscrobblerError> {
+    let username = required(params, "username")?;
+    let api_key = authenticate_signed(params, db).await
+        .map_err(|e| match e.code {
+            ErrorCode::OperationFailed => e,
+            _ => AudioscrobblerError::new(ErrorCode::AuthenticationFailed, &e.message),
+        })?;
+    verify_password(params, username, &api_key)?;
+    let session_key = database::repository::create_session(api_key.id, db).await?;
+    debug!("Created Audioscrobbler session for {}", api_key.name);
+
+    Ok(Element::parent("session", vec![
+        Element::text("name", username),
+        Element::text("key", &session_key),
+        Element::text("subscriber", "0"),
+    ]))
+}
+
+async fn scrobble(params: &HashMap<String, String>, db: &DatabaseConnection) -> Result<Element, AudioscrobblerError> {
+    let api_key = authenticate_session(params, db).await?;
+    let origin = format!("client:{}", api_key.name);
+
+    if submitted_count(params) > MAX_SCROBBLES_PER_REQUEST {
+        return Err(AudioscrobblerError::new(ErrorCode::InvalidParameters, &format!("Too many scrobbles, at most {} are allowed per request", MAX_SCROBBLES_PER_REQUEST)));
+    }
+
+    let mut scrobbles = vec![];
+    let mut responses = vec![];
+    let mut ignored = 0;
+    for index in 0..MAX_SCROBBLES_PER_REQUEST {
+        let Some(scrobble) = parse_scrobble(params, index) else {
+            break;
+        };
+        let mut response = track_info_elements(params, index);
+        match scrobble {
+            Ok(scrobble) => {
+                response.push(Element::text("timestamp", &scrobble.timestamp.to_string()));
+                response.push(ignored_message(IgnoredCode::NotIgnored, ""));
+                scrobbles.push(ScrobbleWrite { origin: Some(origin.clone()), ..scrobble });
+            }
+            Err(e) => {
+                response.push(Element::text("timestamp", indexed(params, "timestamp", index).unwrap_or_default()));
+                response.push(ignored_message(e.code, &e.message));
+                ignored += 1;
+            }
+        }
+        responses.push(Element::parent("scrobble", response));
+    }
+    if responses.is_empty() {
+        return Err(AudioscrobblerError::new(ErrorCode::InvalidParameters, "No scrobbles submitted"));
+    }
+
+    let accepted = scrobbles.len();
+    if !scrobbles.is_empty() {
+        database::repository::create_scrobbles(scrobbles, false, db).await?;
+    }
+
+    Ok(Element {
+        name: "scrobbles",
+        attributes: vec![("accepted", accepted.to_string()), ("ignored", ignored.to_string())],
+        content: Content::Children(responses),
+    })
+}
+
+async fn update_now_playing(params: &HashMap<String, String>, db: &DatabaseConnection) -> Result<Element, AudioscrobblerError> {
+    let api_key = authenticate_session(params, db).await?;
+    required(params, "artist")?;
+    required(params, "track")?;
+    // we don't keep track of currently playing tracks yet
+    debug!("Ignoring now playing update from {}", api_key.name);
+
+    let mut response = track_info_elements(params, 0);
+    response.push(ignored_message(IgnoredCode::NotIgnored, ""));
+    Ok(Element::parent("nowplaying", response))
+}
+
+pub(crate) async fn dispatch(params: HashMap<String, String>, db: &DatabaseConnection) -> Response {
+    let format = match params.get("format").map(|f| f.as_str()) {
+        Some("json") => Format::Json,
+        _ => Format::Xml,
+    };
+    let result = match params.get("method").map(|m| m.as_str()) {
+        Some("auth.getMobileSession") => get_mobile_session(&params, db).await,
+        Some("track.scrobble") => scrobble(&params, db).await,
+        Some("track.updateNowPlaying") => update_now_playing(&params, db).await,
+        _ => Err(AudioscrobblerError::new(ErrorCode::InvalidMethod, "Invalid Method - No method with that name in this package")),
+    };
+    render(format, StatusCode::OK, result)
+}
+
 #[utoipa::path(
     get,
     path = "",
@@ -34,8 +461,8 @@ pub struct ApiDoc;
     summary = "Root Endpoint",
     description = "In accordance with the <a href='https://www.last.fm/api'>specification</a>, this endpoint is used for all operations. The query argument 'method' is used to determine the operation."
 )]
-pub async fn mainendpoint() -> Json<i32> {
-    Json(3)
+pub async fn mainendpoint(State(db): State<DatabaseConnection>, Query(params): Query<HashMap<String, String>>) -> Response {
+    dispatch(params, &db).await
 }
 
 #[utoipa::path(
@@ -48,6 +475,15 @@ pub async fn mainendpoint() -> Json<i32> {
     summary = "Root Endpoint POST",
     description = "In accordance with the <a href='https://www.last.fm/api'>specification</a>, this endpoint is used for all operations. The query argument 'method' is used to determine the operation."
 )]
-pub async fn mainendpoint_post() -> Json<i32> {
-    Json(42)
+pub async fn mainendpoint_post(State(db): State<DatabaseConnection>, Query(mut params): Query<HashMap<String, String>>, body: Bytes) -> Response {
+    // parameters are usually in the form encoded body, but some clients put some of them in the query
+    match serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body) {
+        Ok(body_params) => {
+            params.extend(body_params);
+            dispatch(params, &db).await
+        }
+        Err(e) => {
+            render(Format::Xml, StatusCode::BAD_REQUEST, Err(AudioscrobblerError::new(ErrorCode::InvalidParameters, &e.to_string())))
+        }
+    }
 }
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+use axum::body::Bytes;
+use axum::extract::{OriginalUri, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use log::debug;
+use serde_json::Value;
+use md5::{Digest, Md5};
+use utoipa::OpenApi;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+use sea_orm::DatabaseConnection;
+use crate::api::ScrobbleAPI;
+use crate::server::AppState;
+use crate::database;
+use crate::database::errors::MalojaError;
+use crate::entity::album::AlbumWrite;
+use crate::entity::api_key::ApiKeyRead;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
+
+pub const API: ScrobbleAPI = ScrobbleAPI {
+    prefix: "/audioscrobbler_legacy",
+    tag: "Audioscrobbler Legacy",
+    register: register_routes,
+};
+
+/// Maximum amount of scrobbles in one submission, as defined by the specification
+const MAX_SCROBBLES_PER_REQUEST: usize = 50;
+/// Fields that describe a single submission
+const SCROBBLE_FIELDS: [&str; 8] = ["a", "t", "i", "o", "r", "l", "b", "m"];
+/// How far the client's clock may be off before we reject the handshake
+const MAX_CLOCK_DIFFERENCE_SECONDS: i64 = 60 * 60 * 24;
+
+fn register_routes(mut router: OpenApiRouter<AppState>) -> OpenApiRouter<AppState> {
+    router = router
+        .routes(routes!(handshake))
+        .routes(routes!(nowplaying))
+        .routes(routes!(submissions));
+    router
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(handshake, nowplaying, submissions),
+    info(title = "Audioscrobbler Legacy API", version = "1.2.1")
+)]
+pub struct ApiDoc;
+
+
+/// Plain text replies as defined in the <a href='https://web.archive.org/web/20170107015006/http://www.last.fm/api/submissions'>specification</a>.
+/// All of them are sent with status 200, since clients only look at the body
+pub enum Reply {
+    Ok,
+    Handshake { session_key: String, nowplaying_url: String, submissions_url: String },
+    BadAuth,
+    BadTime,
+    BadSession,
+    Failed(String),
+}
+
+impl IntoResponse for Reply {
+    fn into_response(self) -> Response {
+        let body = match self {
+            Reply::Ok => "OK\n".to_string(),
+            Reply::Handshake { session_key, nowplaying_url, submissions_url } => {
+                format!("OK\n{}\n{}\n{}\n", session_key, nowplaying_url, submissions_url)
+            }
+            Reply::BadAuth => "BADAUTH\n".to_string(),
+            Reply::BadTime => "BADTIME\n".to_string(),
+            Reply::BadSession => "BADSESSION\n".to_string(),
+            Reply::Failed(reason) => format!("FAILED {}\n", reason),
+        };
+        (StatusCode::OK, [(header::CONTENT_TYPE, "text/plain")], body).into_response()
+    }
+}
+
+fn md5_hex(input: &str) -> String {
+    format!("{:x}", Md5::digest(input.as_bytes()))
+}
+
+/// The API key takes the role of the password, so the token is md5(md5(key) + timestamp)
+async fn authenticate_token(token: &str, timestamp: &str, db: &DatabaseConnection) -> Result<Option<ApiKeyRead>, Reply> {
+    let api_keys = database::repository::api_keys(db).await
+        .map_err(|e| Reply::Failed(e.to_string()))?;
+    Ok(api_keys.into_iter().find(|api_key| {
+        md5_hex(&format!("{}{}", md5_hex(&api_key.key), timestamp)).eq_ignore_ascii_case(token)
+    }))
+}
+
+async fn authenticate_session(params: &HashMap<String, String>, db: &DatabaseConnection) -> Result<ApiKeyRead, Reply> {
+    let session_key = params.get("s").ok_or(Reply::BadSession)?;
+    database::repository::validate_session(session_key, db).await.map_err(|_| Reply::BadSession)
+}
+
+fn parse_form(body: &Bytes) -> Result<HashMap<String, String>, Reply> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .map(|params| params.into_iter().collect())
+        .map_err(|e| Reply::Failed(format!("Invalid form data: {}", e)))
+}
+
+/// Base URL under which the client reached us, so that the returned URLs also work behind a reverse proxy
+fn base_url(headers: &HeaderMap, uri: &OriginalUri) -> String {
+    let host = headers.get(header::HOST).and_then(|h| h.to_str().ok()).unwrap_or("localhost");
+    let scheme = headers.get("X-Forwarded-Proto").and_then(|h| h.to_str().ok()).unwrap_or("http");
+    format!("{}://{}{}", scheme, host, uri.path().trim_end_matches('/'))
+}
+
+fn field<'a>(params: &'a HashMap<String, String>, key: &str) -> Option<&'a str> {
+    params.get(key).map(|value| value.as_str()).filter(|value| !value.is_empty())
+}
+
+/// Gets a field for one specific scrobble. Stored raw scrobbles only contain one, without index
+fn indexed<'a>(params: &'a HashMap<String, String>, key: &str, index: usize) -> Option<&'a str> {
+    field(params, &format!("{}[{}]", key, index)).or(if index == 0 { field(params, key) } else { None })
+}
+
+/// The fields of one submission, without index. This is what we keep as raw scrobble
+fn scrobble_params(params: &HashMap<String, String>, index: usize) -> HashMap<String, String> {
+    SCROBBLE_FIELDS.iter()
+        .filter_map(|key| indexed(params, key, index).map(|value| (key.to_string(), value.to_string())))
+        .collect()
+}
+
+/// Parses a stored raw scrobble, which only contains the fields of that submission
+pub fn parse_raw(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let params: HashMap<String, String> = serde_json::from_value(payload.clone())?;
+    match parse_scrobble(&params, 0) {
+        Some(Ok(scrobble)) => Ok(scrobble),
+        Some(Err(Reply::Failed(message))) => Err(MalojaError::ParseError { message }),
+        _ => Err(MalojaError::ParseError { message: "Raw scrobble has no artist".to_string() }),
+    }
+}
+
+fn parse_scrobble(params: &HashMap<String, String>, index: usize) -> Option<Result<ScrobbleWrite, Reply>> {
+    let indexed = |key: &str| indexed(params, key, index);
+    // no artist means there is no scrobble at this index
+    let artist = indexed("a")?;
+    let Some(title) = indexed("t") else {
+        return Some(Err(Reply::Failed(format!("Missing track title for submission {}", index))));
+    };
+    let Some(timestamp) = indexed("i").and_then(|t| t.parse::<i64>().ok()) else {
+        return Some(Err(Reply::Failed(format!("Missing or invalid time for submission {}", index))));
+    };
+
+    let artists = vec![ArtistWrite {
+        id: None,
+        name: Some(artist.to_string()),
+        mbid: None,
+        spotify_id: None,
+    }];
+    let album = indexed("b").map(|album_title| AlbumWrite {
+        id: None,
+        album_title: Some(album_title.to_string()),
+        // the protocol has no album artists, so we assume it's an album by the track artists
+        album_artists: Some(artists.clone()),
+        mbid: None,
+        spotify_id: None,
+    });
+
+    Some(Ok(ScrobbleWrite {
+        timestamp,
+        track: TrackWrite {
+            id: None,
+            title: Some(title.to_string()),
+            primary_artists: Some(artists),
+            secondary_artists: None,
+            track_length: indexed("l").and_then(|l| l.parse().ok()),
+            album,
+            mbid: indexed("m").map(|m| m.to_string()),
+            spotify_id: None,
+        },
+        origin: None,
+        listen_duration: None,
+        raw_scrobble: Some(RawScrobble::new(
+            ScrobbleSource::AudioscrobblerLegacy,
+            serde_json::to_value(scrobble_params(params, index)).expect("Fields are valid json"),
+        )),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "",
+    params(
+        ("hs" = String, Query, description = "Must be `true`"),
+        ("p" = String, Query, description = "Protocol version, `1.2` or `1.2.1`"),
+        ("c" = Option<String>, Query, description = "Client identifier"),
+        ("v" = Option<String>, Query, description = "Client version"),
+        ("u" = String, Query, description = "Username, only used for display"),
+        ("t" = i64, Query, description = "Current unix timestamp"),
+        ("a" = String, Query, description = "Authentication token `md5(md5(<API key>) + <t>)`"),
+    ),
+    responses(
+        (status = 200, content_type = "text/plain", description = "`OK` with session key and submission URLs, or `BADAUTH`, `BADTIME` or `FAILED <reason>`"),
+    ),
+    summary = "Handshake",
+    description = "Exchanges the authentication token for a session key that is used for all other requests."
+)]
+pub async fn handshake(State(db): State<DatabaseConnection>, Query(params): Query<HashMap<String, String>>, headers: HeaderMap, uri: OriginalUri) -> Reply {
+    if field(&params, "hs") != Some("true") {
+        return Reply::Failed("Not a handshake request".to_string());
+    }
+    if !matches!(field(&params, "p"), Some("1.2") | Some("1.2.1")) {
+        return Reply::Failed("Unsupported protocol version".to_string());
+    }
+    let (Some(username), Some(timestamp), Some(token)) = (field(&params, "u"), field(&params, "t"), field(&params, "a")) else {
+        return Reply::BadAuth;
+    };
+    let Ok(client_time) = timestamp.parse::<i64>() else {
+        return Reply::BadTime;
+    };
+    if (chrono::Utc::now().timestamp() - client_time).abs() > MAX_CLOCK_DIFFERENCE_SECONDS {
+        return Reply::BadTime;
+    }
+
+    let api_key = match authenticate_token(token, timestamp, &db).await {
+        Ok(Some(api_key)) => api_key,
+        Ok(None) => return Reply::BadAuth,
+        Err(reply) => return reply,
+    };
+    let session_key = match database::repository::create_session(api_key.id, &db).await {
+        Ok(session_key) => session_key,
+        Err(e) => return Reply::Failed(e.to_string()),
+    };
+    debug!("Created legacy Audioscrobbler session for {} (user {})", api_key.name, username);
+
+    let base = base_url(&headers, &uri);
+    Reply::Handshake {
+        session_key,
+        nowplaying_url: format!("{}/nowplaying", base),
+        submissions_url: format!("{}/submissions", base),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/nowplaying",
+    request_body(content_type = "application/x-www-form-urlencoded", description = "Fields `s`, `a`, `t`, `b`, `l`, `n`, `m`"),
+    responses(
+        (status = 200, content_type = "text/plain", description = "`OK`, `BADSESSION` or `FAILED <reason>`"),
+    ),
+    summary = "Now Playing",
+)]
+pub async fn nowplaying(State(db): State<DatabaseConnection>, body: Bytes) -> Reply {
+    let params = match parse_form(&body) {
+        Ok(params) => params,
+        Err(reply) => return reply,
+    };
+    let api_key = match authenticate_session(&params, &db).await {
+        Ok(api_key) => api_key,
+        Err(reply) => return reply,
+    };
+    if field(&params, "a").is_none() || field(&params, "t").is_none() {
+        return Reply::Failed("Missing artist or track title".to_string());
+    }
+    // we don't keep track of currently playing tracks yet
+    debug!("Ignoring now playing update from {}", api_key.name);
+    Reply::Ok
+}
+
+#[utoipa::path(
+    post,
+    path = "/submissions",
+    request_body(content_type = "application/x-www-form-urlencoded", description = "Field `s` and indexed fields `a[0]`, `t[0]`, `i[0]`, `o[0]`, `r[0]`, `l[0]`, `b[0]`, `n[0]`, `m[0]`"),
+    responses(
+        (status = 200, content_type = "text/plain", description = "`OK`, `BADSESSION` or `FAILED <reason>`"),
+    ),
+    summary = "Submissions",
+)]
+pub async fn submissions(State(db): State<DatabaseConnection>, body: Bytes) -> Reply {
+    let params = match parse_form(&body) {
+        Ok(params) => params,
+        Err(reply) => return reply,
+    };
+    let api_key = match authenticate_session(&params, &db).await {
+        Ok(api_key) => api_key,
+        Err(reply) => return reply,
+    };
+    let origin = format!("client:{}", api_key.name);
+
+    let mut scrobbles = vec![];
+    for index in 0..MAX_SCROBBLES_PER_REQUEST {
+        match parse_scrobble(&params, index) {
+            Some(Ok(scrobble)) => scrobbles.push(ScrobbleWrite { origin: Some(origin.clone()), ..scrobble }),
+            Some(Err(reply)) => return reply,
+            None => break,
+        }
+    }
+    if scrobbles.is_empty() {
+        return Reply::Failed("No submissions".to_string());
+    }
+
+    match database::repository::create_scrobbles(scrobbles, false, &db).await {
+        Ok(_) => Reply::Ok,
+        Err(e) => Reply::Failed(e.to_string()),
+    }
+}
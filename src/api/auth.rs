@@ -0,0 +1,61 @@
+use axum::extract::{FromRef, FromRequestParts};
+use axum::http::header::AUTHORIZATION;
+use axum::http::HeaderMap;
+use axum::http::request::Parts;
+
+use sea_orm::DatabaseConnection;
+use subtle::ConstantTimeEq;
+
+use crate::configuration::ADMIN_PASSWORD;
+use crate::database;
+use crate::database::errors::MalojaError;
+use crate::entity::api_key::ApiKeyRead;
+
+/// Extracts the credential from an `Authorization` header with the given scheme, e.g. `Token abc` or `Bearer abc`
+pub fn authorization_token<'a>(headers: &'a HeaderMap, scheme: &str) -> Option<&'a str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (found_scheme, token) = value.trim().split_once(' ')?;
+    found_scheme.eq_ignore_ascii_case(scheme).then(|| token.trim())
+}
+
+/// Extractor for endpoints that may only be used by the admin. Expects the admin password as a `Bearer` token
+pub struct AdminAuth;
+
+impl<S> FromRequestParts<S> for AdminAuth
+where
+    S: Send + Sync,
+{
+    type Rejection = MalojaError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match authorization_token(&parts.headers, "Bearer") {
+            // the time this takes shouldn't tell how much of the password was right
+            Some(password) if bool::from(password.as_bytes().ct_eq(ADMIN_PASSWORD.as_bytes())) => Ok(AdminAuth),
+            Some(_) => Err(MalojaError::AuthenticationError { message: "Invalid admin password".to_string() }),
+            None => Err(MalojaError::AuthenticationError { message: "This endpoint requires the admin password as Bearer token".to_string() }),
+        }
+    }
+}
+
+/// Extractor for endpoints that accept any API key, either as `Token` in the `Authorization` header
+/// or as `key` query argument
+pub struct ClientAuth(pub ApiKeyRead);
+
+impl<S> FromRequestParts<S> for ClientAuth
+where
+    S: Send + Sync,
+    DatabaseConnection: FromRef<S>,
+{
+    type Rejection = MalojaError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let db = DatabaseConnection::from_ref(state);
+        let query_key = parts.uri.query()
+            .and_then(|query| serde_urlencoded::from_str::<Vec<(String, String)>>(query).ok())
+            .and_then(|params| params.into_iter().find(|(name, _)| name == "key").map(|(_, value)| value));
+        let key = authorization_token(&parts.headers, "Token").map(|token| token.to_string())
+            .or(query_key)
+            .ok_or(MalojaError::AuthenticationError { message: "This endpoint requires an API key".to_string() })?;
+        Ok(ClientAuth(database::repository::validate_api_key(&key, &db).await?))
+    }
+}
@@ -1,9 +1,26 @@
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::Json;
-use utoipa::OpenApi;
+use axum::response::{IntoResponse, Response};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 
+use sea_orm::DatabaseConnection;
+use crate::api::auth::authorization_token;
 use crate::api::ScrobbleAPI;
+use crate::server::AppState;
+use crate::database;
+use crate::database::errors::MalojaError;
+use crate::entity::album::AlbumWrite;
+use crate::entity::api_key::ApiKeyRead;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
 
 pub const API: ScrobbleAPI = ScrobbleAPI {
     prefix: "/listenbrainz/1",
@@ -12,7 +29,10 @@ pub const API: ScrobbleAPI = ScrobbleAPI {
     register: register_routes,
 };
 
-fn register_routes(mut router: OpenApiRouter) -> OpenApiRouter {
+/// Maximum amount of listens in one submission, as defined by the ListenBrainz server
+const MAX_LISTENS_PER_REQUEST: usize = 1000;
+
+fn register_routes(mut router: OpenApiRouter<AppState>) -> OpenApiRouter<AppState> {
     router = router.routes(routes!(submit, validate));
     router
 }
@@ -24,20 +44,286 @@ fn register_routes(mut router: OpenApiRouter) -> OpenApiRouter {
 )]
 pub struct ApiDoc;
 
+
+#[derive(Deserialize, ToSchema, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenType {
+    Single,
+    PlayingNow,
+    Import,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone)]
+pub struct SubmitListens {
+    listen_type: ListenType,
+    payload: Vec<Listen>,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone)]
+pub struct Listen {
+    /// Required for `single` and `import`, must be omitted for `playing_now`
+    #[schema(examples(1443521965))]
+    listened_at: Option<i64>,
+    track_metadata: TrackMetadata,
+}
+
+#[derive(Deserialize, ToSchema, Debug, Clone)]
+pub struct TrackMetadata {
+    #[schema(examples("Rick Astley"))]
+    artist_name: String,
+    #[schema(examples("Never Gonna Give You Up"))]
+    track_name: String,
+    #[schema(examples("Whenever You Need Somebody"))]
+    release_name: Option<String>,
+    #[serde(default)]
+    additional_info: AdditionalInfo,
+}
+
+/// The subset of the optional metadata that we actually use, everything else is ignored
+#[derive(Deserialize, ToSchema, Debug, Clone, Default)]
+pub struct AdditionalInfo {
+    /// Individual artists, if the client can provide them separately from the credit string
+    artist_names: Option<Vec<String>>,
+    artist_mbids: Option<Vec<String>>,
+    release_artist_name: Option<String>,
+    release_artist_names: Option<Vec<String>>,
+    release_mbid: Option<String>,
+    recording_mbid: Option<String>,
+    duration_ms: Option<u64>,
+    duration: Option<u64>,
+    #[schema(examples("navidrome"))]
+    submission_client: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ListenBrainzStatus {
+    #[schema(examples("ok"))]
+    status: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TokenValidation {
+    #[schema(examples(200))]
+    code: u16,
+    #[schema(examples("Token valid."))]
+    message: String,
+    valid: bool,
+    /// Name of the API key
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(examples("Navidrome"))]
+    user_name: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in=Query)]
+pub struct QueryToken {
+    /// Deprecated way to pass the token, the `Authorization: Token <token>` header takes precedence
+    token: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ListenBrainzError {
+    #[schema(examples(400))]
+    code: u16,
+    #[schema(examples("JSON document does not contain any listens"))]
+    error: String,
+}
+
+impl ListenBrainzError {
+    fn bad_request(error: &str) -> Self {
+        ListenBrainzError { code: StatusCode::BAD_REQUEST.as_u16(), error: error.to_string() }
+    }
+    fn unauthorized(error: &str) -> Self {
+        ListenBrainzError { code: StatusCode::UNAUTHORIZED.as_u16(), error: error.to_string() }
+    }
+}
+
+impl IntoResponse for ListenBrainzError {
+    fn into_response(self) -> Response {
+        let code = StatusCode::from_u16(self.code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (code, Json(self)).into_response()
+    }
+}
+
+impl From<MalojaError> for ListenBrainzError {
+    fn from(e: MalojaError) -> Self {
+        match e {
+            MalojaError::ParseError { message } => ListenBrainzError::bad_request(&message),
+            MalojaError::AuthenticationError { .. } => ListenBrainzError::unauthorized("Invalid authorization token."),
+            e => ListenBrainzError { code: StatusCode::INTERNAL_SERVER_ERROR.as_u16(), error: e.to_string() },
+        }
+    }
+}
+
+/// Converts a single listen to our internal representation. Fails if there is no timestamp, so this can't
+/// be used for `playing_now` listens
+pub fn listen_to_scrobble(listen: &Listen) -> Result<ScrobbleWrite, MalojaError> {
+    let timestamp = listen.listened_at.ok_or(MalojaError::ParseError {
+        message: "Listen is missing listened_at".to_string(),
+    })?;
+    let metadata = &listen.track_metadata;
+    let info = &metadata.additional_info;
+
+    // mbids can only be assigned if they actually correspond to the individual artists
+    let artist_names = info.artist_names.clone().unwrap_or_else(|| vec![metadata.artist_name.clone()]);
+    let artist_mbids = info.artist_mbids.clone()
+        .filter(|mbids| mbids.len() == artist_names.len())
+        .map(|mbids| mbids.into_iter().map(Some).collect())
+        .unwrap_or_else(|| vec![None; artist_names.len()]);
+    let artists: Vec<ArtistWrite> = artist_names.into_iter().zip(artist_mbids).map(|(name, mbid)| {
+        ArtistWrite {
+            id: None,
+            name: Some(name),
+            mbid,
+            spotify_id: None,
+        }
+    }).collect();
+
+    let album = metadata.release_name.clone().map(|release_name| {
+        let album_artists = match (&info.release_artist_names, &info.release_artist_name) {
+            (Some(names), _) => names.clone(),
+            (None, Some(name)) => vec![name.clone()],
+            (None, None) => vec![],
+        };
+        AlbumWrite {
+            id: None,
+            album_title: Some(release_name),
+            // without explicit album artists we assume it's an album by the track artists
+            album_artists: Some(if album_artists.is_empty() {
+                artists.clone()
+            } else {
+                album_artists.into_iter().map(|name| ArtistWrite {
+                    id: None,
+                    name: Some(name),
+                    mbid: None,
+                    spotify_id: None,
+                }).collect()
+            }),
+            mbid: info.release_mbid.clone(),
+            spotify_id: None,
+        }
+    });
+
+    let track_length = info.duration_ms.map(|ms| ms / 1000).or(info.duration);
+
+    Ok(ScrobbleWrite {
+        timestamp,
+        track: TrackWrite {
+            id: None,
+            title: Some(metadata.track_name.clone()),
+            primary_artists: Some(artists),
+            secondary_artists: None,
+            track_length: track_length.map(|l| l as u32),
+            album,
+            mbid: info.recording_mbid.clone(),
+            spotify_id: None,
+        },
+        origin: info.submission_client.clone(),
+        listen_duration: None,
+        raw_scrobble: None,
+    })
+}
+
+/// Parses a listen as it was submitted, keeping it as raw scrobble. Also used to reparse stored listens
+pub fn parse_listen(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let listen: Listen = serde_json::from_value(payload.clone())?;
+    Ok(ScrobbleWrite {
+        raw_scrobble: Some(RawScrobble::new(ScrobbleSource::Listenbrainz, payload.clone())),
+        ..listen_to_scrobble(&listen)?
+    })
+}
+
+async fn authenticate(headers: &HeaderMap, db: &DatabaseConnection) -> Result<ApiKeyRead, ListenBrainzError> {
+    let token = authorization_token(headers, "Token")
+        .ok_or(ListenBrainzError::unauthorized("You need to provide an Authorization header."))?;
+    Ok(database::repository::validate_api_key(token, db).await?)
+}
+
+fn validate_submission(submission: &SubmitListens) -> Result<(), ListenBrainzError> {
+    if submission.payload.is_empty() {
+        return Err(ListenBrainzError::bad_request("JSON document does not contain any listens"));
+    }
+    match submission.listen_type {
+        ListenType::Single | ListenType::PlayingNow if submission.payload.len() > 1 => {
+            Err(ListenBrainzError::bad_request("JSON document contains more than one listen"))
+        }
+        ListenType::Import if submission.payload.len() > MAX_LISTENS_PER_REQUEST => {
+            Err(ListenBrainzError::bad_request("Too many listens"))
+        }
+        ListenType::PlayingNow if submission.payload[0].listened_at.is_some() => {
+            Err(ListenBrainzError::bad_request("JSON document must not contain listened_at while submitting playing_now"))
+        }
+        ListenType::Single | ListenType::Import if submission.payload.iter().any(|l| l.listened_at.is_none()) => {
+            Err(ListenBrainzError::bad_request("JSON document must contain listened_at"))
+        }
+        _ => Ok(()),
+    }
+}
+
 #[utoipa::path(
     post,
     path = "/submit-listens",
-    responses((status = OK))
+    request_body = SubmitListens,
+    params(("Authorization" = String, Header, description = "API key in the format `Token <key>`")),
+    responses(
+        (status = OK, body = inline(ListenBrainzStatus), description = "Listens were accepted"),
+        (status = BAD_REQUEST, body = inline(ListenBrainzError), description = "Submission is not a valid ListenBrainz payload"),
+        (status = UNAUTHORIZED, body = inline(ListenBrainzError), description = "Missing or invalid API key"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(ListenBrainzError), description = "Server error while handling the request"),
+    )
 )]
-pub async fn submit() -> Json<i32> {
-    Json(3)
+pub async fn submit(State(db): State<DatabaseConnection>, headers: HeaderMap, body: Bytes) -> Result<Json<ListenBrainzStatus>, ListenBrainzError> {
+    let api_key = authenticate(&headers, &db).await?;
+    // parse manually instead of using the Json extractor, since not all clients bother with the content type
+    let document: Value = serde_json::from_slice(&body)
+        .map_err(|e| ListenBrainzError::bad_request(&format!("Invalid JSON document submitted: {}", e)))?;
+    let submission: SubmitListens = serde_json::from_value(document.clone())
+        .map_err(|e| ListenBrainzError::bad_request(&format!("Invalid JSON document submitted: {}", e)))?;
+    validate_submission(&submission)?;
+
+    if submission.listen_type == ListenType::PlayingNow {
+        // we don't keep track of currently playing tracks yet
+        debug!("Ignoring playing_now submission from {}", api_key.name);
+    }
+    else {
+        // the individual listens as submitted, including everything we don't parse (yet)
+        let listens = document["payload"].as_array().cloned().unwrap_or_default();
+        let scrobbles = listens.iter().map(parse_listen).collect::<Result<Vec<ScrobbleWrite>, MalojaError>>()?;
+        database::repository::create_scrobbles(scrobbles, false, &db).await?;
+    }
+
+    Ok(Json(ListenBrainzStatus { status: "ok".to_string() }))
 }
 
 #[utoipa::path(
     get,
     path = "/validate-token",
-    responses((status = OK))
+    params(
+        ("Authorization" = Option<String>, Header, description = "API key in the format `Token <key>`"),
+        QueryToken
+    ),
+    responses(
+        (status = OK, body = inline(TokenValidation), description = "Validity of the token"),
+        (status = BAD_REQUEST, body = inline(ListenBrainzError), description = "No token was supplied"),
+    )
 )]
-pub async fn validate() -> Json<i32> {
-    Json(3)
+pub async fn validate(State(db): State<DatabaseConnection>, headers: HeaderMap, Query(params_token): Query<QueryToken>) -> Result<Json<TokenValidation>, ListenBrainzError> {
+    let token = authorization_token(&headers, "Token")
+        .or(params_token.token.as_deref())
+        .ok_or(ListenBrainzError::bad_request("You need to provide an Authorization token."))?;
+    match database::repository::validate_api_key(token, &db).await {
+        Ok(api_key) => Ok(Json(TokenValidation {
+            code: StatusCode::OK.as_u16(),
+            message: "Token valid.".to_string(),
+            valid: true,
+            user_name: Some(api_key.name),
+        })),
+        Err(MalojaError::AuthenticationError { .. }) => Ok(Json(TokenValidation {
+            code: StatusCode::OK.as_u16(),
+            message: "Token invalid.".to_string(),
+            valid: false,
+            user_name: None,
+        })),
+        Err(e) => Err(e.into()),
+    }
 }
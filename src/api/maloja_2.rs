@@ -1,27 +1,38 @@
 use std::error::Error;
-use axum::extract::{FromRequestParts, Query};
+use std::io;
+use axum::body::Body;
+use axum::extract::{FromRequest, FromRequestParts, Query, Request, State};
 use axum::extract::path::ErrorKind;
-use axum::extract::rejection::{PathRejection};
+use axum::extract::rejection::{JsonRejection, PathRejection};
 use axum::http::request::Parts;
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::Json;
 use axum::response::{IntoResponse, Response};
 use axum::routing::any;
+use futures_util::stream;
+use log::error;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use utoipa::{OpenApi, ToSchema};
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_axum::routes;
 
+use sea_orm::DatabaseConnection;
+use crate::api::auth::{AdminAuth, ClientAuth};
 use crate::api::ScrobbleAPI;
+use crate::server::AppState;
 use crate::database;
 use crate::database::errors::MalojaError;
+use crate::database::export::{Export, ExportFormat};
 use crate::entity::artist::{ArtistRead};
 use crate::entity::track::{TrackRead};
-use crate::entity::scrobble::{ScrobbleRead};
+use crate::entity::scrobble::{RawScrobble, ScrobbleRead, ScrobbleSource, ScrobbleWrite};
 use crate::entity::album::{AlbumRead};
-use crate::database::views::{Charts, Paginated, PaginationInfo, PerformanceEntry, PulseEntry};
-use crate::uri::{PathEntity, QueryLimitAlbum, QueryLimitArtist, QueryLimitTrack, QueryPagination, QueryTimerange, QueryTimesteps};
+use crate::entity::api_key::{ApiKeyRead, ApiKeyWrite};
+use crate::database::views::{Charts, Paginated, PaginationInfo, PerformanceEntry, PulseEntry, ReparseResult, ImportReport, BackupInfo, TimeRangeInfo};
+use crate::timeranges::ALL_TIME;
+use crate::uri::{PathEntity, QueryLimitAlbum, QueryLimitArtist, QueryLimitTrack, QueryPagination, QuerySubmission, QueryTimerange, QueryTimesteps};
 
 pub const API: ScrobbleAPI = ScrobbleAPI {
     prefix: "/maloja_2",
@@ -29,7 +40,7 @@ pub const API: ScrobbleAPI = ScrobbleAPI {
     register: register_routes,
 };
 
-fn register_routes(mut router: OpenApiRouter) -> OpenApiRouter {
+fn register_routes(mut router: OpenApiRouter<AppState>) -> OpenApiRouter<AppState> {
     router = router
         .routes(routes!(info_artist))
         .routes(routes!(info_track))
@@ -37,9 +48,17 @@ fn register_routes(mut router: OpenApiRouter) -> OpenApiRouter {
         .routes(routes!(charts_tracks))
         .routes(routes!(charts_artists))
         .routes(routes!(charts_albums))
-        .routes(routes!(scrobbles))
+        .routes(routes!(scrobbles, submit_scrobbles))
         .routes(routes!(pulse))
         .routes(routes!(performance))
+        .routes(routes!(api_keys, create_api_key))
+        .routes(routes!(reparse))
+        .routes(routes!(imports))
+        .routes(routes!(backups, create_backup))
+        .routes(routes!(export))
+        .routes(routes!(export_csv))
+        .routes(routes!(export_listenbrainz))
+        .routes(routes!(delete_api_key))
         //.fallback(notfound); // TODO: https://github.com/tokio-rs/axum/issues/3138
         .route("/{*rest}", any(notfound));
     router
@@ -48,9 +67,10 @@ fn register_routes(mut router: OpenApiRouter) -> OpenApiRouter {
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(charts_tracks, charts_artists, charts_albums, info_artist, info_album, info_track, scrobbles, pulse, performance),
+    paths(charts_tracks, charts_artists, charts_albums, info_artist, info_album, info_track, scrobbles, submit_scrobbles, pulse, performance,
+        api_keys, create_api_key, delete_api_key, reparse, imports, backups, create_backup, export, export_csv, export_listenbrainz),
     info(title = "Maloja API", version = "2"),
-    components(schemas(ScrobbleRead,TrackRead,ArtistRead,AlbumRead))
+    components(schemas(ScrobbleRead,TrackRead,ArtistRead,AlbumRead,ApiKeyRead,ScrobbleWrite))
 )]
 pub struct ApiDoc;
 
@@ -81,6 +101,11 @@ impl IntoResponse for MalojaError {
             MalojaError::ArtistNotFound { id } => create_response(&self, StatusCode::NOT_FOUND, format!("Artist {} not found", id)),
             MalojaError::TrackNotFound { id } => create_response(&self, StatusCode::NOT_FOUND, format!("Track {} not found", id)),
             MalojaError::AlbumNotFound { id } => create_response(&self, StatusCode::NOT_FOUND, format!("Album {} not found", id)),
+            MalojaError::ArtistNameNotFound { name } => create_response(&self, StatusCode::NOT_FOUND, format!("Artist {} not found", name)),
+            MalojaError::TrackTitleNotFound { title } => create_response(&self, StatusCode::NOT_FOUND, format!("Track {} not found", title)),
+            MalojaError::ApiKeyNotFound { id } => create_response(&self, StatusCode::NOT_FOUND, format!("API key {} not found", id)),
+            MalojaError::ScrobbleExists { timestamp } => create_response(&self, StatusCode::CONFLICT, format!("Scrobble at {} already exists", timestamp)),
+            MalojaError::AuthenticationError { message } => create_response(&self, StatusCode::UNAUTHORIZED, message.clone()),
             MalojaError::DatabaseConnectionError { message } => create_response(&self, StatusCode::INTERNAL_SERVER_ERROR, message.clone()),
             MalojaError::ParseError { message } => create_response(&self, StatusCode::BAD_REQUEST, message.clone()),
             e => create_response(&self, StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
@@ -138,6 +163,29 @@ where
     }
 }
 
+// custom json body extractor, same reason as above
+struct JsonBody<T>(T);
+
+impl<S, T> FromRequest<S> for JsonBody<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, Json<APIError>);
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match Json::<T>::from_request(req, state).await {
+            Ok(value) => Ok(Self(value.0)),
+            Err(rejection) => {
+                Err((rejection.status(), Json(APIError {
+                    error_type: "RequestBodyParseError".to_string(),
+                    description: rejection.body_text(),
+                })))
+            }
+        }
+    }
+}
+
 pub async fn notfound() -> Response {
     (StatusCode::NOT_FOUND, Json(APIError {
         error_type: "InvalidEndpoint".to_string(),
@@ -156,8 +204,8 @@ pub async fn notfound() -> Response {
         (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
     )
 )]
-async fn info_artist(Path(params_path): Path<PathEntity>) -> Result<(StatusCode, Json<ArtistRead>), MalojaError> {
-    let result = database::repository::artist_info(params_path.id).await?;
+async fn info_artist(State(db): State<DatabaseConnection>, Path(params_path): Path<PathEntity>) -> Result<(StatusCode, Json<ArtistRead>), MalojaError> {
+    let result = database::repository::artist_info(params_path.id, &db).await?;
     Ok((StatusCode::OK, Json(result)))
 
 }
@@ -172,8 +220,8 @@ async fn info_artist(Path(params_path): Path<PathEntity>) -> Result<(StatusCode,
         (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
     )
 )]
-async fn info_track(Path(params_path): Path<PathEntity>) -> Result<(StatusCode, Json<TrackRead>), MalojaError> {
-    let result = database::repository::track_info(params_path.id).await?;
+async fn info_track(State(db): State<DatabaseConnection>, Path(params_path): Path<PathEntity>) -> Result<(StatusCode, Json<TrackRead>), MalojaError> {
+    let result = database::repository::track_info(params_path.id, &db).await?;
     Ok((StatusCode::OK, Json(result)))
 }
 
@@ -187,8 +235,8 @@ async fn info_track(Path(params_path): Path<PathEntity>) -> Result<(StatusCode,
         (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
     )
 )]
-async fn info_album(Path(params_path): Path<PathEntity>) -> Result<(StatusCode, Json<AlbumRead>), MalojaError> {
-    let result = database::repository::album_info(params_path.id).await?;
+async fn info_album(State(db): State<DatabaseConnection>, Path(params_path): Path<PathEntity>) -> Result<(StatusCode, Json<AlbumRead>), MalojaError> {
+    let result = database::repository::album_info(params_path.id, &db).await?;
     Ok((StatusCode::OK, Json(result)))
 }
 
@@ -205,6 +253,7 @@ async fn info_album(Path(params_path): Path<PathEntity>) -> Result<(StatusCode,
     )
 )]
 async fn charts_tracks(
+    State(db): State<DatabaseConnection>,
     Query(params_time): Query<QueryTimerange>,
     Query(params_limit_artist): Query<QueryLimitArtist>,
     Query(params_limit_album): Query<QueryLimitAlbum>
@@ -212,7 +261,8 @@ async fn charts_tracks(
     let timerange = params_time.to_timerange()?;
     let artist_id = params_limit_artist.to_artist_id();
     let album_id = params_limit_album.to_album_id();
-    let tracks = database::repository::charts_tracks(timerange, artist_id, album_id).await?;
+    let time_range = TimeRangeInfo::new(&timerange);
+    let tracks = database::repository::charts_tracks(timerange, artist_id, album_id, &db).await?;
     Ok((StatusCode::OK, Json(Charts {
         pagination: PaginationInfo {
             page: 1,
@@ -220,6 +270,7 @@ async fn charts_tracks(
             items_per_page: tracks.len() as u32,
             items_total: tracks.len() as u32,
         },
+        time_range,
         result: tracks
     })))
 }
@@ -234,10 +285,12 @@ async fn charts_tracks(
     )
 )]
 async fn charts_artists(
+    State(db): State<DatabaseConnection>,
     Query(params_time): Query<QueryTimerange>
 ) -> Result<(StatusCode, Json<Charts<ArtistRead>>), MalojaError> {
     let timerange = params_time.to_timerange()?;
-    let artists = database::repository::charts_artists(timerange).await?;
+    let time_range = TimeRangeInfo::new(&timerange);
+    let artists = database::repository::charts_artists(timerange, &db).await?;
     Ok((StatusCode::OK, Json(Charts {
         pagination: PaginationInfo {
             page: 1,
@@ -245,6 +298,7 @@ async fn charts_artists(
             items_per_page: artists.len() as u32,
             items_total: artists.len() as u32,
         },
+        time_range,
         result: artists
     })))
 }
@@ -259,12 +313,14 @@ async fn charts_artists(
     )
 )]
 async fn charts_albums(
+    State(db): State<DatabaseConnection>,
     Query(params_time): Query<QueryTimerange>,
     Query(params_limit_artist): Query<QueryLimitArtist>
 ) -> Result<(StatusCode, Json<Charts<AlbumRead>>), MalojaError> {
     let timerange = params_time.to_timerange()?;
     let artist_id = params_limit_artist.to_artist_id();
-    let albums = database::repository::charts_albums(timerange, artist_id).await?;
+    let time_range = TimeRangeInfo::new(&timerange);
+    let albums = database::repository::charts_albums(timerange, artist_id, &db).await?;
     Ok((StatusCode::OK, Json(Charts {
         pagination: PaginationInfo {
             page: 1,
@@ -272,6 +328,7 @@ async fn charts_albums(
             items_per_page: albums.len() as u32,
             items_total: albums.len() as u32,
         },
+        time_range,
         result: albums
     })))
 }
@@ -288,6 +345,7 @@ async fn charts_albums(
     )
 )]
 async fn scrobbles(
+    State(db): State<DatabaseConnection>,
     Query(params_time): Query<QueryTimerange>,
     Query(params_limit_artist): Query<QueryLimitArtist>,
     Query(params_limit_album): Query<QueryLimitAlbum>,
@@ -298,11 +356,60 @@ async fn scrobbles(
     let artist_id = params_limit_artist.to_artist_id();
     let album_id = params_limit_album.to_album_id();
     let track_id = params_limit_track.to_track_id();
-    let scrobbles = database::repository::scrobbles(timerange, artist_id, album_id, track_id, true).await?;
+    let scrobbles = database::repository::scrobbles(timerange, artist_id, album_id, track_id, true, &db).await?;
     let paginated_scrobbles = params_pagination.paginate_results(scrobbles);
     Ok((StatusCode::OK, Json(paginated_scrobbles)))
 }
 
+/// Either a single scrobble or a list of them
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+enum ScrobbleSubmission {
+    Single(Box<ScrobbleWrite>),
+    Batch(Vec<ScrobbleWrite>),
+}
+
+#[utoipa::path(
+    post,
+    path = "/scrobbles",
+    params(
+        ("Authorization" = Option<String>, Header, description = "API key in the format `Token <key>`"),
+        ("key" = Option<String>, Query, description = "API key, if not supplied in the header"),
+        QuerySubmission
+    ),
+    request_body = inline(ScrobbleSubmission),
+    responses(
+        (status = CREATED, body = Vec<ScrobbleRead>, description = "Scrobbles were created (or already existed)"),
+        (status = BAD_REQUEST, body = inline(APIError), description = "Submission could not be parsed"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or invalid API key"),
+        (status = CONFLICT, body = inline(APIError), description = "A scrobble already exists and fail_on_existing was set"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn submit_scrobbles(
+    State(db): State<DatabaseConnection>,
+    ClientAuth(api_key): ClientAuth,
+    Query(params_submission): Query<QuerySubmission>,
+    JsonBody(input): JsonBody<ScrobbleSubmission>
+) -> Result<(StatusCode, Json<Vec<ScrobbleRead>>), MalojaError> {
+    let scrobbles = match input {
+        ScrobbleSubmission::Single(scrobble) => vec![*scrobble],
+        ScrobbleSubmission::Batch(scrobbles) => scrobbles,
+    };
+    let scrobbles = scrobbles.into_iter().map(|scrobble| ScrobbleWrite {
+        raw_scrobble: Some(RawScrobble::new(ScrobbleSource::MalojaV2, serde_json::to_value(&scrobble).expect("Scrobble is valid json"))),
+        origin: scrobble.origin.clone().or(Some(format!("client:{}", api_key.name))),
+        ..scrobble
+    }).collect();
+    let result = database::repository::submit_scrobbles(scrobbles, params_submission.fail_on_existing(), &db).await?;
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+/// Parses a stored raw scrobble that was submitted to this API
+pub fn parse_raw(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    Ok(serde_json::from_value(payload.clone())?)
+}
+
 #[utoipa::path(
     get,
     path = "/pulse",
@@ -313,6 +420,7 @@ async fn scrobbles(
     )
 )]
 async fn pulse(
+    State(db): State<DatabaseConnection>,
     Query(params_time): Query<QueryTimerange>,
     Query(params_timesteps): Query<QueryTimesteps>,
     Query(params_limit_artist): Query<QueryLimitArtist>,
@@ -326,7 +434,7 @@ async fn pulse(
     let album_id = params_limit_album.to_album_id();
     let track_id = params_limit_track.to_track_id();
 
-    let result = database::repository::pulse(subranges, artist_id, album_id, track_id).await?;
+    let result = database::repository::pulse(subranges, artist_id, album_id, track_id, &db).await?;
     let paginated_pulse = params_pagination.paginate_results(result);
     Ok((StatusCode::OK, Json(paginated_pulse)))
 
@@ -342,6 +450,7 @@ async fn pulse(
     )
 )]
 async fn performance(
+    State(db): State<DatabaseConnection>,
     Query(params_time): Query<QueryTimerange>,
     Query(params_timesteps): Query<QueryTimesteps>,
     Query(params_limit_artist): Query<QueryLimitArtist>,
@@ -355,8 +464,197 @@ async fn performance(
     let album_id = params_limit_album.to_album_id();
     let track_id = params_limit_track.to_track_id();
 
-    let result = database::repository::performance(subranges, artist_id, album_id, track_id).await?;
+    let result = database::repository::performance(subranges, artist_id, album_id, track_id, &db).await?;
     let paginated_pulse = params_pagination.paginate_results(result);
     Ok((StatusCode::OK, Json(paginated_pulse)))
 
-}
\ No newline at end of file
+}
+
+#[utoipa::path(
+    get,
+    path = "/apikeys",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`")),
+    responses(
+        (status = OK, body = Vec<ApiKeyRead>, description = "Successful request"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn api_keys(State(db): State<DatabaseConnection>, _admin: AdminAuth) -> Result<(StatusCode, Json<Vec<ApiKeyRead>>), MalojaError> {
+    let result = database::repository::api_keys(&db).await?;
+    Ok((StatusCode::OK, Json(result)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/apikeys",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`")),
+    request_body = ApiKeyWrite,
+    responses(
+        (status = CREATED, body = ApiKeyRead, description = "Key was created"),
+        (status = BAD_REQUEST, body = inline(APIError), description = "Invalid key information or key already exists"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn create_api_key(State(db): State<DatabaseConnection>, _admin: AdminAuth, JsonBody(input): JsonBody<ApiKeyWrite>) -> Result<(StatusCode, Json<ApiKeyRead>), MalojaError> {
+    let result = database::repository::create_api_key(input, &db).await?;
+    Ok((StatusCode::CREATED, Json(result)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/apikeys/{id}",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`"), PathEntity),
+    responses(
+        (status = NO_CONTENT, description = "Key was deleted"),
+        (status = NOT_FOUND, body = inline(APIError), description = "Key ID does not exist in database"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn delete_api_key(State(db): State<DatabaseConnection>, _admin: AdminAuth, Path(params_path): Path<PathEntity>) -> Result<StatusCode, MalojaError> {
+    database::repository::delete_api_key(params_path.id, &db).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/reparse",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`")),
+    responses(
+        (status = OK, body = ReparseResult, description = "All scrobbles with a stored raw scrobble were parsed again"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn reparse(State(db): State<DatabaseConnection>, _admin: AdminAuth) -> Result<(StatusCode, Json<ReparseResult>), MalojaError> {
+    let result = database::repository::reparse_scrobbles(crate::api::parse_raw_scrobble, &db).await?;
+    Ok((StatusCode::OK, Json(result)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/imports",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`")),
+    responses(
+        (status = OK, body = Vec<ImportReport>, description = "Progress of all imports since the server was started"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+    )
+)]
+async fn imports(_admin: AdminAuth) -> Result<(StatusCode, Json<Vec<ImportReport>>), MalojaError> {
+    Ok((StatusCode::OK, Json(database::import::import_status())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/backups",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`")),
+    responses(
+        (status = OK, body = Vec<BackupInfo>, description = "All database backups, newest first"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn backups(_admin: AdminAuth) -> Result<(StatusCode, Json<Vec<BackupInfo>>), MalojaError> {
+    Ok((StatusCode::OK, Json(database::backups::list_backups()?)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/backups",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`")),
+    responses(
+        (status = CREATED, body = BackupInfo, description = "Backup was written to the backup folder. It is not deleted automatically"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn create_backup(State(db): State<DatabaseConnection>, _admin: AdminAuth) -> Result<(StatusCode, Json<BackupInfo>), MalojaError> {
+    let path = database::backup_database(&db, "manual").await?;
+    Ok((StatusCode::CREATED, Json(database::backups::backup_info(&path)?)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`")),
+    responses(
+        (status = OK, content_type = "application/json", description = "All scrobbles in the export format of the original Maloja, which both versions can import"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn export(State(db): State<DatabaseConnection>, _admin: AdminAuth) -> Result<Response, MalojaError> {
+    let export = Export::new(ExportFormat::Maloja, ALL_TIME, None, None, None)?;
+    Ok(export_response(export, db))
+}
+
+/// Sends the export as a download, with the same name it would get when exported to the data folder.
+/// The body is streamed while the scrobbles are read, so errors after the first chunk can only abort the download
+fn export_response(export: Export, db: DatabaseConnection) -> Response {
+    let format = export.format();
+    let path = database::export::default_export_path(format);
+    let file_name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let disposition = format!("attachment; filename=\"{}\"", file_name);
+    let chunks = stream::try_unfold((export, db), |(mut export, db)| async move {
+        match export.next_chunk(&db).await {
+            Ok(chunk) => Ok(chunk.map(|chunk| (chunk, (export, db)))),
+            Err(e) => {
+                error!("Export failed: {:?}", e);
+                Err(io::Error::other(format!("{:?}", e)))
+            }
+        }
+    });
+    (StatusCode::OK, [(header::CONTENT_TYPE, format.content_type().to_string()), (header::CONTENT_DISPOSITION, disposition)], Body::from_stream(chunks)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/export/csv",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`"),
+        QueryTimerange, QueryLimitArtist, QueryLimitAlbum, QueryLimitTrack),
+    responses(
+        (status = OK, content_type = "text/csv", description = "Scrobbles as CSV with a header row, from old to new"),
+        (status = BAD_REQUEST, body = inline(APIError), description = "Invalid time range, or more than one of artist, album and track"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn export_csv(
+    State(db): State<DatabaseConnection>,
+    _admin: AdminAuth,
+    Query(params_time): Query<QueryTimerange>,
+    Query(params_limit_artist): Query<QueryLimitArtist>,
+    Query(params_limit_album): Query<QueryLimitAlbum>,
+    Query(params_limit_track): Query<QueryLimitTrack>,
+) -> Result<Response, MalojaError> {
+    let timerange = params_time.to_timerange()?;
+    let export = Export::new(ExportFormat::Csv, timerange, params_limit_artist.to_artist_id(), params_limit_album.to_album_id(), params_limit_track.to_track_id())?;
+    Ok(export_response(export, db))
+}
+
+#[utoipa::path(
+    get,
+    path = "/export/listenbrainz",
+    params(("Authorization" = String, Header, description = "Admin password in the format `Bearer <password>`"),
+        QueryTimerange, QueryLimitArtist, QueryLimitAlbum, QueryLimitTrack),
+    responses(
+        (status = OK, content_type = "application/jsonl", description = "Scrobbles as ListenBrainz listens, one per line, from old to new"),
+        (status = BAD_REQUEST, body = inline(APIError), description = "Invalid time range, or more than one of artist, album and track"),
+        (status = UNAUTHORIZED, body = inline(APIError), description = "Missing or wrong admin password"),
+        (status = INTERNAL_SERVER_ERROR, body = inline(APIError), description = "Server error while handling the request"),
+    )
+)]
+async fn export_listenbrainz(
+    State(db): State<DatabaseConnection>,
+    _admin: AdminAuth,
+    Query(params_time): Query<QueryTimerange>,
+    Query(params_limit_artist): Query<QueryLimitArtist>,
+    Query(params_limit_album): Query<QueryLimitAlbum>,
+    Query(params_limit_track): Query<QueryLimitTrack>,
+) -> Result<Response, MalojaError> {
+    let timerange = params_time.to_timerange()?;
+    let export = Export::new(ExportFormat::Listenbrainz, timerange, params_limit_artist.to_artist_id(), params_limit_album.to_album_id(), params_limit_track.to_track_id())?;
+    Ok(export_response(export, db))
+}
@@ -0,0 +1,794 @@
+use axum::body::Bytes;
+use axum::extract::{RawQuery, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use utoipa::{OpenApi, ToSchema};
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+use sea_orm::DatabaseConnection;
+use crate::api::ScrobbleAPI;
+use crate::server::AppState;
+use crate::configuration::CONFIG;
+use crate::database;
+use crate::database::errors::MalojaError;
+use crate::database::views::ChartsEntry;
+use crate::entity::album::AlbumRead;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::album::AlbumWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleRead, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::{TrackRead, TrackWrite};
+use crate::timeranges::{BaseTimeRange, RangeType, TimeRange, ALL_TIME};
+use crate::uri::QueryTimerange;
+
+pub const API: ScrobbleAPI = ScrobbleAPI {
+    prefix: "/mlj_1",
+    tag: "Maloja v1",
+    register: register_routes,
+};
+
+fn register_routes(mut router: OpenApiRouter<AppState>) -> OpenApiRouter<AppState> {
+    router = router
+        .routes(routes!(serverinfo))
+        .routes(routes!(scrobbles))
+        .routes(routes!(charts_artists))
+        .routes(routes!(charts_tracks))
+        .routes(routes!(pulse))
+        .routes(routes!(performance))
+        .routes(routes!(top_artists))
+        .routes(routes!(artistinfo))
+        .routes(routes!(trackinfo))
+        .routes(routes!(newscrobble, newscrobble_post));
+    router
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(serverinfo, scrobbles, charts_artists, charts_tracks, pulse, performance, top_artists, artistinfo, trackinfo,
+        newscrobble, newscrobble_post),
+    info(title = "Maloja API", version = "1", description = "Legacy API of the original Maloja, kept for existing scripts and dashboards. \
+        Entities are referred to by name instead of ID. Time arguments are `since`, `to` and `in` with values like \
+        `2024`, `2024/10`, `2024/10/05`, `2024w12`, `today`, `yesterday`, `thisweek`, `lastweek`, `thismonth`, `lastmonth`, \
+        `thisyear`, `lastyear` or `alltime`. `in` also accepts rolling ranges like `last7days` or `last12months`, \
+        `since` and `to` also UNIX timestamps and ISO 8601 datetimes."),
+)]
+pub struct ApiDoc;
+
+
+#[derive(Serialize, ToSchema)]
+#[schema(title = "Error (v1)")]
+pub struct V1Error {
+    #[schema(examples("failure"))]
+    status: String,
+    error: V1ErrorInfo,
+    #[serde(skip)]
+    code: StatusCode,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1ErrorInfo {
+    #[serde(rename = "type")]
+    #[schema(examples("entity_does_not_exist"))]
+    error_type: String,
+    #[schema(examples("This artist does not exist in the database."))]
+    desc: String,
+}
+
+impl V1Error {
+    fn new(code: StatusCode, error_type: &str, desc: &str) -> Self {
+        V1Error {
+            status: "failure".to_string(),
+            error: V1ErrorInfo { error_type: error_type.to_string(), desc: desc.to_string() },
+            code,
+        }
+    }
+}
+
+impl IntoResponse for V1Error {
+    fn into_response(self) -> Response {
+        (self.code, Json(self)).into_response()
+    }
+}
+
+impl From<MalojaError> for V1Error {
+    fn from(e: MalojaError) -> Self {
+        match e {
+            MalojaError::ArtistNotFound { .. } | MalojaError::ArtistNameNotFound { .. } => {
+                V1Error::new(StatusCode::NOT_FOUND, "entity_does_not_exist", "This artist does not exist in the database.")
+            }
+            MalojaError::TrackNotFound { .. } | MalojaError::TrackTitleNotFound { .. } => {
+                V1Error::new(StatusCode::NOT_FOUND, "entity_does_not_exist", "This track does not exist in the database.")
+            }
+            MalojaError::AuthenticationError { message } => V1Error::new(StatusCode::UNAUTHORIZED, "authentication_fail", &message),
+            MalojaError::ScrobbleExists { .. } => {
+                V1Error::new(StatusCode::CONFLICT, "duplicate_timestamp", "A scrobble is already registered with this timestamp.")
+            }
+            MalojaError::ParseError { message } => V1Error::new(StatusCode::BAD_REQUEST, "malformed_request", &message),
+            e => V1Error::new(StatusCode::INTERNAL_SERVER_ERROR, "unknown_error", &e.to_string()),
+        }
+    }
+}
+
+/// Arguments as the v1 API takes them. Keys can be repeated (e.g. multiple `artist`), so this is a list instead of a map
+struct V1Params(Vec<(String, String)>);
+
+impl V1Params {
+    fn from_query(query: Option<String>) -> Result<Self, V1Error> {
+        let params = serde_urlencoded::from_str(&query.unwrap_or_default())
+            .map_err(|e| V1Error::new(StatusCode::BAD_REQUEST, "malformed_request", &e.to_string()))?;
+        Ok(V1Params(params))
+    }
+
+    /// First non-empty value of any of the keys
+    fn get(&self, keys: &[&str]) -> Option<&str> {
+        self.0.iter()
+            .find(|(key, value)| keys.contains(&key.as_str()) && !value.is_empty())
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn get_all(&self, keys: &[&str]) -> Vec<String> {
+        self.0.iter()
+            .filter(|(key, value)| keys.contains(&key.as_str()) && !value.is_empty())
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    fn get_number<T: std::str::FromStr>(&self, keys: &[&str]) -> Result<Option<T>, MalojaError> {
+        self.get(keys).map(|value| value.parse::<T>().map_err(|_| MalojaError::ParseError {
+            message: format!("Argument {} must be a number", keys[0]),
+        })).transpose()
+    }
+
+    fn get_flag(&self, key: &str) -> bool {
+        matches!(self.get(&[key]), Some("true") | Some("1") | Some("yes"))
+    }
+
+    fn timerange(&self) -> Result<TimeRange, MalojaError> {
+        // alltime stands for the open end of a range
+        let given = |keys: &[&str]| self.get(keys).filter(|value| *value != "alltime");
+        if let Some(within) = self.get(&["in", "within", "during"]) {
+            return match within {
+                "alltime" => Ok(ALL_TIME),
+                within => QueryTimerange::match_during(within),
+            };
+        }
+        QueryTimerange::match_bounds(given(&["since", "from", "start"]), given(&["to", "until", "end"]))
+    }
+
+    /// Splits the time range into `step` units, grouped by `stepn`. With `trail`, each returned range also covers
+    /// the previous ones, with `cumulative` everything since the start
+    fn steps(&self) -> Result<Vec<TimeRange>, MalojaError> {
+        let step = match self.get(&["step"]).unwrap_or("month") {
+            "day" => RangeType::Day,
+            "week" => RangeType::Week,
+            "month" => RangeType::Month,
+            "year" => RangeType::Year,
+            _ => return Err(MalojaError::ParseError { message: "Unknown step".to_string() }),
+        };
+        let stepn: usize = self.get_number(&["stepn"])?.unwrap_or(1).max(1);
+        let trail: usize = self.get_number(&["trail"])?.unwrap_or(1).max(1);
+        let cumulative = self.get_flag("cumulative");
+
+        let units: Vec<BaseTimeRange> = self.timerange()?.get_subranges(step).into_iter().filter_map(|range| match range {
+            TimeRange::Simple(base) => Some(base),
+            _ => None,
+        }).collect();
+        let groups: Vec<(BaseTimeRange, BaseTimeRange)> = units.chunks(stepn)
+            .map(|chunk| (chunk[0].clone(), chunk[chunk.len() - 1].clone()))
+            .collect();
+
+        Ok((0..groups.len()).map(|index| {
+            let first = if cumulative { 0 } else { index.saturating_sub(trail - 1) };
+            if first == index && stepn == 1 {
+                TimeRange::Simple(groups[index].1.clone())
+            }
+            else {
+                TimeRange::Composite { start: Some(groups[first].0.clone()), end: Some(groups[index].1.clone()) }
+            }
+        }).collect())
+    }
+
+    async fn artist_id(&self, db: &DatabaseConnection) -> Result<Option<u32>, MalojaError> {
+        match self.get(&["artist"]) {
+            Some(name) => Ok(Some(database::repository::artist_by_name(name, db).await?.id)),
+            None => Ok(None),
+        }
+    }
+
+    async fn track_id(&self, db: &DatabaseConnection) -> Result<Option<u32>, MalojaError> {
+        match self.get(&["title"]) {
+            Some(title) => Ok(Some(database::repository::track_by_title(&self.get_all(&["artist"]), title, db).await?.id)),
+            None => Ok(None),
+        }
+    }
+
+    /// Pages are counted from 0 in this API, and there is no limit by default
+    fn paginate<T>(&self, results: Vec<T>) -> Result<Vec<T>, MalojaError> {
+        let Some(per_page) = self.get_number::<usize>(&["perpage", "max"])? else {
+            return Ok(results);
+        };
+        let page: usize = self.get_number(&["page"])?.unwrap_or(0);
+        Ok(results.into_iter().skip(page.saturating_mul(per_page)).take(per_page).collect())
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1List<T> {
+    #[schema(examples("ok"))]
+    status: String,
+    list: Vec<T>,
+}
+
+impl<T> V1List<T> {
+    fn new(list: Vec<T>) -> Self {
+        V1List { status: "ok".to_string(), list }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1Album {
+    #[schema(examples("Square One"))]
+    albumtitle: String,
+    artists: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1Track {
+    artists: Vec<String>,
+    #[schema(examples("Whistle"))]
+    title: String,
+    album: Option<V1Album>,
+    #[schema(examples(212))]
+    length: Option<u32>,
+}
+
+impl From<AlbumRead> for V1Album {
+    fn from(album: AlbumRead) -> Self {
+        V1Album {
+            albumtitle: album.album_title,
+            artists: album.album_artists.into_iter().map(|a| a.name).collect(),
+        }
+    }
+}
+
+impl From<TrackRead> for V1Track {
+    fn from(track: TrackRead) -> Self {
+        V1Track {
+            artists: track.artists.into_iter().map(|a| a.name).collect(),
+            title: track.title,
+            album: track.album.map(V1Album::from),
+            length: track.track_length,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1Scrobble {
+    #[schema(examples(1700000000))]
+    time: i64,
+    track: V1Track,
+}
+
+impl From<ScrobbleRead> for V1Scrobble {
+    fn from(scrobble: ScrobbleRead) -> Self {
+        V1Scrobble { time: scrobble.timestamp, track: scrobble.track.into() }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1ArtistChartsEntry {
+    #[schema(examples("BLACKPINK"))]
+    artist: String,
+    artist_id: u32,
+    scrobbles: u32,
+    rank: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1TrackChartsEntry {
+    track: V1Track,
+    track_id: u32,
+    scrobbles: u32,
+    rank: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1PulseEntry {
+    #[schema(value_type = String, examples("2024/3"))]
+    range: TimeRange,
+    scrobbles: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1PerformanceEntry {
+    #[schema(value_type = String, examples("2024/3"))]
+    range: TimeRange,
+    /// `null` if there were no scrobbles in this range
+    rank: Option<u32>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1TopArtistEntry {
+    #[schema(value_type = String, examples("2024/3"))]
+    range: TimeRange,
+    /// `null` if there were no scrobbles in this range
+    artist: Option<String>,
+    artist_id: Option<u32>,
+    scrobbles: u32,
+}
+
+/// Years in which the entity was first, second or third of the yearly charts
+#[derive(Serialize, ToSchema, Default)]
+pub struct V1Medals {
+    #[schema(value_type = Vec<String>)]
+    gold: Vec<TimeRange>,
+    #[schema(value_type = Vec<String>)]
+    silver: Vec<TimeRange>,
+    #[schema(value_type = Vec<String>)]
+    bronze: Vec<TimeRange>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1ArtistInfo {
+    #[schema(examples("BLACKPINK"))]
+    artist: String,
+    scrobbles: u32,
+    /// All-time rank, `null` without scrobbles
+    position: Option<usize>,
+    medals: V1Medals,
+    id: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1TrackInfo {
+    track: V1Track,
+    scrobbles: u32,
+    /// All-time rank, `null` without scrobbles
+    position: Option<usize>,
+    medals: V1Medals,
+    /// `gold`, `platinum` or `diamond`, depending on the configured thresholds
+    certification: Option<String>,
+    id: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1DatabaseStatus {
+    healthy: bool,
+    rebuildinprogress: bool,
+    complete: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1ServerInfo {
+    #[schema(examples("Maloja"))]
+    name: String,
+    version: Vec<u32>,
+    #[schema(examples("0.1.0"))]
+    versionstring: String,
+    db_status: V1DatabaseStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1ScrobbleResult {
+    #[schema(examples("success"))]
+    status: String,
+    track: V1ScrobbledTrack,
+    #[schema(examples("Scrobbled Whistle by BLACKPINK"))]
+    desc: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct V1ScrobbledTrack {
+    artists: Vec<String>,
+    title: String,
+}
+
+/// Medals only count finished years
+async fn medals(artist_id: Option<u32>, track_id: Option<u32>, db: &DatabaseConnection) -> Result<V1Medals, MalojaError> {
+    let now = Utc::now().timestamp();
+    let mut years = ALL_TIME.get_subranges(RangeType::Year);
+    // all time ends with the last scrobble, which isn't necessarily this year
+    years.retain(|year| year.timestamp_boundaries().1 < now);
+    let mut medals = V1Medals::default();
+    for entry in database::repository::performance(years, artist_id, None, track_id, db).await? {
+        match entry.rank {
+            1 => medals.gold.push(entry.time_range),
+            2 => medals.silver.push(entry.time_range),
+            3 => medals.bronze.push(entry.time_range),
+            _ => {}
+        }
+    }
+    Ok(medals)
+}
+
+fn certification(scrobbles: u32) -> Option<String> {
+    if scrobbles >= CONFIG.scrobbles_track_diamond as u32 {
+        Some("diamond".to_string())
+    } else if scrobbles >= CONFIG.scrobbles_track_platinum as u32 {
+        Some("platinum".to_string())
+    } else if scrobbles >= CONFIG.scrobbles_track_gold as u32 {
+        Some("gold".to_string())
+    } else {
+        None
+    }
+}
+
+
+#[utoipa::path(
+    get,
+    path = "/serverinfo",
+    responses(
+        (status = OK, body = V1ServerInfo, description = "Successful request"),
+    )
+)]
+async fn serverinfo() -> Json<V1ServerInfo> {
+    let versionstring = env!("CARGO_PKG_VERSION").to_string();
+    Json(V1ServerInfo {
+        name: "Maloja".to_string(),
+        version: versionstring.split('.').filter_map(|part| part.parse().ok()).collect(),
+        versionstring,
+        db_status: V1DatabaseStatus { healthy: true, rebuildinprogress: false, complete: true },
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/scrobbles",
+    params(
+        ("artist" = Option<String>, Query, description = "Limit to this artist, or together with title to this track (can be repeated)"),
+        ("title" = Option<String>, Query, description = "Limit to this track"),
+        ("since" = Option<String>, Query), ("to" = Option<String>, Query), ("in" = Option<String>, Query),
+        ("perpage" = Option<u32>, Query), ("page" = Option<u32>, Query, description = "Starts at 0"),
+    ),
+    responses(
+        (status = OK, body = inline(V1List<V1Scrobble>), description = "Successful request"),
+        (status = NOT_FOUND, body = V1Error, description = "Artist or track does not exist"),
+    )
+)]
+async fn scrobbles(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1List<V1Scrobble>>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let timerange = params.timerange()?;
+    let (artist_id, track_id) = match params.track_id(&db).await? {
+        Some(track_id) => (None, Some(track_id)),
+        None => (params.artist_id(&db).await?, None),
+    };
+    let result = database::repository::scrobbles(timerange, artist_id, None, track_id, true, &db).await?;
+    let result = params.paginate(result)?;
+    Ok(Json(V1List::new(result.into_iter().map(V1Scrobble::from).collect())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/charts/artists",
+    params(("since" = Option<String>, Query), ("to" = Option<String>, Query), ("in" = Option<String>, Query)),
+    responses(
+        (status = OK, body = inline(V1List<V1ArtistChartsEntry>), description = "Successful request"),
+    )
+)]
+async fn charts_artists(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1List<V1ArtistChartsEntry>>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let result = database::repository::charts_artists(params.timerange()?, &db).await?;
+    Ok(Json(V1List::new(result.into_iter().map(|entry| V1ArtistChartsEntry {
+        artist: entry.entry.name,
+        artist_id: entry.entry.id,
+        scrobbles: entry.scrobbles,
+        rank: entry.rank,
+    }).collect())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/charts/tracks",
+    params(
+        ("artist" = Option<String>, Query, description = "Only tracks by this artist"),
+        ("since" = Option<String>, Query), ("to" = Option<String>, Query), ("in" = Option<String>, Query)
+    ),
+    responses(
+        (status = OK, body = inline(V1List<V1TrackChartsEntry>), description = "Successful request"),
+        (status = NOT_FOUND, body = V1Error, description = "Artist does not exist"),
+    )
+)]
+async fn charts_tracks(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1List<V1TrackChartsEntry>>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let result: Vec<ChartsEntry<TrackRead>> = database::repository::charts_tracks(params.timerange()?, params.artist_id(&db).await?, None, &db).await?;
+    Ok(Json(V1List::new(result.into_iter().map(|entry| V1TrackChartsEntry {
+        track_id: entry.entry.id,
+        track: entry.entry.into(),
+        scrobbles: entry.scrobbles,
+        rank: entry.rank,
+    }).collect())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/pulse",
+    params(
+        ("artist" = Option<String>, Query), ("title" = Option<String>, Query),
+        ("since" = Option<String>, Query), ("to" = Option<String>, Query), ("in" = Option<String>, Query),
+        ("step" = Option<String>, Query, description = "`day`, `week`, `month` (default) or `year`"),
+        ("stepn" = Option<u32>, Query, description = "Amount of steps per entry"),
+        ("trail" = Option<u32>, Query, description = "Amount of entries each entry also covers"),
+        ("cumulative" = Option<bool>, Query, description = "Each entry covers everything since the start"),
+    ),
+    responses(
+        (status = OK, body = inline(V1List<V1PulseEntry>), description = "Successful request"),
+        (status = NOT_FOUND, body = V1Error, description = "Artist or track does not exist"),
+    )
+)]
+async fn pulse(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1List<V1PulseEntry>>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let (artist_id, track_id) = match params.track_id(&db).await? {
+        Some(track_id) => (None, Some(track_id)),
+        None => (params.artist_id(&db).await?, None),
+    };
+    let result = database::repository::pulse(params.steps()?, artist_id, None, track_id, &db).await?;
+    Ok(Json(V1List::new(result.into_iter().map(|entry| V1PulseEntry {
+        range: entry.time_range,
+        scrobbles: entry.scrobbles,
+    }).collect())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/performance",
+    params(
+        ("artist" = String, Query), ("title" = Option<String>, Query),
+        ("since" = Option<String>, Query), ("to" = Option<String>, Query), ("in" = Option<String>, Query),
+        ("step" = Option<String>, Query), ("stepn" = Option<u32>, Query), ("trail" = Option<u32>, Query), ("cumulative" = Option<bool>, Query),
+    ),
+    responses(
+        (status = OK, body = inline(V1List<V1PerformanceEntry>), description = "Successful request"),
+        (status = BAD_REQUEST, body = V1Error, description = "Neither artist nor track was specified"),
+        (status = NOT_FOUND, body = V1Error, description = "Artist or track does not exist"),
+    )
+)]
+async fn performance(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1List<V1PerformanceEntry>>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let (artist_id, track_id) = match params.track_id(&db).await? {
+        Some(track_id) => (None, Some(track_id)),
+        None => (params.artist_id(&db).await?, None),
+    };
+    if artist_id.is_none() && track_id.is_none() {
+        return Err(MalojaError::ParseError { message: "Performance needs an artist or a track".to_string() }.into());
+    }
+    let result = database::repository::performance(params.steps()?, artist_id, None, track_id, &db).await?;
+    Ok(Json(V1List::new(result.into_iter().map(|entry| V1PerformanceEntry {
+        range: entry.time_range,
+        rank: (entry.rank > 0).then_some(entry.rank),
+    }).collect())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/top/artists",
+    params(
+        ("since" = Option<String>, Query), ("to" = Option<String>, Query), ("in" = Option<String>, Query),
+        ("step" = Option<String>, Query), ("stepn" = Option<u32>, Query), ("trail" = Option<u32>, Query), ("cumulative" = Option<bool>, Query),
+    ),
+    responses(
+        (status = OK, body = inline(V1List<V1TopArtistEntry>), description = "Successful request"),
+    )
+)]
+async fn top_artists(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1List<V1TopArtistEntry>>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let mut result = vec![];
+    for range in params.steps()? {
+        let top = database::repository::charts_artists(range.clone(), &db).await?.into_iter().next();
+        result.push(V1TopArtistEntry {
+            range,
+            scrobbles: top.as_ref().map(|entry| entry.scrobbles).unwrap_or(0),
+            artist_id: top.as_ref().map(|entry| entry.entry.id),
+            artist: top.map(|entry| entry.entry.name),
+        });
+    }
+    Ok(Json(V1List::new(result)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/artistinfo",
+    params(("artist" = String, Query)),
+    responses(
+        (status = OK, body = V1ArtistInfo, description = "Successful request"),
+        (status = NOT_FOUND, body = V1Error, description = "Artist does not exist"),
+    )
+)]
+async fn artistinfo(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1ArtistInfo>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let name = params.get(&["artist"])
+        .ok_or(MalojaError::ParseError { message: "Missing argument artist".to_string() })?;
+    let artist = database::repository::artist_by_name(name, &db).await?;
+    let charts = database::repository::charts_artists(ALL_TIME, &db).await?;
+    let entry = charts.iter().find(|entry| entry.entry.id == artist.id);
+    Ok(Json(V1ArtistInfo {
+        scrobbles: entry.map(|entry| entry.scrobbles).unwrap_or(0),
+        position: entry.map(|entry| entry.rank),
+        medals: medals(Some(artist.id), None, &db).await?,
+        id: artist.id,
+        artist: artist.name,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/trackinfo",
+    params(("artist" = Vec<String>, Query, description = "Can be repeated for tracks with multiple artists"), ("title" = String, Query)),
+    responses(
+        (status = OK, body = V1TrackInfo, description = "Successful request"),
+        (status = NOT_FOUND, body = V1Error, description = "Track does not exist"),
+    )
+)]
+async fn trackinfo(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1TrackInfo>, V1Error> {
+    let params = V1Params::from_query(query)?;
+    let title = params.get(&["title"])
+        .ok_or(MalojaError::ParseError { message: "Missing argument title".to_string() })?;
+    let track = database::repository::track_by_title(&params.get_all(&["artist"]), title, &db).await?;
+    let charts = database::repository::charts_tracks(ALL_TIME, None, None, &db).await?;
+    let entry = charts.iter().find(|entry| entry.entry.id == track.id);
+    let scrobbles = entry.map(|entry| entry.scrobbles).unwrap_or(0);
+    Ok(Json(V1TrackInfo {
+        scrobbles,
+        position: entry.map(|entry| entry.rank),
+        medals: medals(None, Some(track.id), &db).await?,
+        certification: certification(scrobbles),
+        id: track.id,
+        track: track.into(),
+    }))
+}
+
+/// Form and JSON bodies are both accepted. JSON lists are treated like repeated arguments
+fn parse_body(headers: &HeaderMap, body: &Bytes) -> Result<Vec<(String, String)>, V1Error> {
+    if body.is_empty() {
+        return Ok(vec![]);
+    }
+    let is_json = headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return serde_urlencoded::from_bytes(body)
+            .map_err(|e| V1Error::new(StatusCode::BAD_REQUEST, "malformed_request", &e.to_string()));
+    }
+    let Value::Object(object) = serde_json::from_slice(body)
+        .map_err(|e| V1Error::new(StatusCode::BAD_REQUEST, "malformed_request", &e.to_string()))? else {
+        return Err(V1Error::new(StatusCode::BAD_REQUEST, "malformed_request", "Body must be a JSON object"));
+    };
+    Ok(object_to_params(object))
+}
+
+fn object_to_params(object: Map<String, Value>) -> Vec<(String, String)> {
+    let scalar = |value: Value| match value {
+        Value::String(string) => Some(string),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        _ => None,
+    };
+    let mut result = vec![];
+    for (key, value) in object {
+        match value {
+            Value::Array(values) => result.extend(values.into_iter().filter_map(scalar).map(|v| (key.clone(), v))),
+            value => result.extend(scalar(value).map(|v| (key.clone(), v))),
+        }
+    }
+    result
+}
+
+/// Parses a stored raw scrobble, which contains the submitted arguments
+pub fn parse_raw(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let Value::Object(object) = payload.clone() else {
+        return Err(MalojaError::ParseError { message: "Raw scrobble is not an object".to_string() });
+    };
+    parse_scrobble(&V1Params(object_to_params(object)))
+        .map_err(|e| MalojaError::ParseError { message: e.error.desc })
+}
+
+fn parse_scrobble(params: &V1Params) -> Result<ScrobbleWrite, V1Error> {
+    let artists = params.get_all(&["artist", "artists"]);
+    let (false, Some(title)) = (artists.is_empty(), params.get(&["title"])) else {
+        return Err(V1Error::new(StatusCode::BAD_REQUEST, "missing_scrobble_data", "The scrobble is missing needed parameters."));
+    };
+    let to_writes = |names: Vec<String>| names.into_iter().map(|name| ArtistWrite {
+        id: None,
+        name: Some(name),
+        mbid: None,
+        spotify_id: None,
+    }).collect::<Vec<ArtistWrite>>();
+    let album_artists = params.get_all(&["albumartists", "albumartist"]);
+    let album = params.get(&["album"]).map(|album_title| AlbumWrite {
+        id: None,
+        album_title: Some(album_title.to_string()),
+        album_artists: Some(to_writes(if album_artists.is_empty() { artists.clone() } else { album_artists })),
+        mbid: None,
+        spotify_id: None,
+    });
+    let timestamp = params.get_number(&["time"])?.unwrap_or_else(|| Utc::now().timestamp());
+
+    // keep everything except the key, and make sure the timestamp is fixed for reparsing
+    let mut raw = Map::new();
+    for (key, value) in params.0.iter().filter(|(key, _)| key != "key" && key != "time") {
+        match raw.get_mut(key) {
+            Some(Value::Array(values)) => values.push(Value::String(value.clone())),
+            _ => { raw.insert(key.clone(), Value::Array(vec![Value::String(value.clone())])); }
+        }
+    }
+    raw.insert("time".to_string(), Value::from(timestamp));
+
+    Ok(ScrobbleWrite {
+        timestamp,
+        track: TrackWrite {
+            id: None,
+            title: Some(title.to_string()),
+            primary_artists: Some(to_writes(artists)),
+            secondary_artists: None,
+            track_length: params.get_number(&["length"])?,
+            album,
+            mbid: None,
+            spotify_id: None,
+        },
+        origin: None,
+        listen_duration: params.get_number(&["duration"])?,
+        raw_scrobble: Some(RawScrobble::new(ScrobbleSource::MalojaV1, Value::Object(raw))),
+    })
+}
+
+async fn scrobble(params: V1Params, db: &DatabaseConnection) -> Result<Json<V1ScrobbleResult>, V1Error> {
+    let key = params.get(&["key"])
+        .ok_or(MalojaError::AuthenticationError { message: "Invalid or missing API key".to_string() })?;
+    let api_key = database::repository::validate_api_key(key, db).await
+        .map_err(|_| MalojaError::AuthenticationError { message: "Invalid or missing API key".to_string() })?;
+
+    let scrobble = ScrobbleWrite {
+        origin: Some(format!("client:{}", api_key.name)),
+        ..parse_scrobble(&params)?
+    };
+    let result = database::repository::submit_scrobbles(vec![scrobble], false, db).await?;
+    let track = result.into_iter().next().expect("One scrobble was submitted").track;
+    let artists: Vec<String> = track.artists.into_iter().map(|a| a.name).collect();
+    Ok(Json(V1ScrobbleResult {
+        status: "success".to_string(),
+        desc: format!("Scrobbled {} by {}", track.title, artists.join(", ")),
+        track: V1ScrobbledTrack { artists, title: track.title },
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/newscrobble",
+    params(
+        ("key" = String, Query, description = "API key"),
+        ("artist" = Vec<String>, Query, description = "Can be repeated for multiple artists"),
+        ("title" = String, Query),
+        ("album" = Option<String>, Query),
+        ("albumartists" = Option<Vec<String>>, Query, description = "Defaults to the track artists"),
+        ("duration" = Option<u32>, Query, description = "Listened seconds"),
+        ("length" = Option<u32>, Query, description = "Track length in seconds"),
+        ("time" = Option<i64>, Query, description = "Unix timestamp, defaults to now"),
+    ),
+    responses(
+        (status = OK, body = V1ScrobbleResult, description = "Scrobble was created"),
+        (status = BAD_REQUEST, body = V1Error, description = "Missing scrobble data"),
+        (status = UNAUTHORIZED, body = V1Error, description = "Missing or invalid API key"),
+    )
+)]
+async fn newscrobble(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery) -> Result<Json<V1ScrobbleResult>, V1Error> {
+    scrobble(V1Params::from_query(query)?, &db).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/newscrobble",
+    request_body(content_type = "application/json", description = "Same arguments as the GET variant, as JSON object or form data"),
+    responses(
+        (status = OK, body = V1ScrobbleResult, description = "Scrobble was created"),
+        (status = BAD_REQUEST, body = V1Error, description = "Missing scrobble data"),
+        (status = UNAUTHORIZED, body = V1Error, description = "Missing or invalid API key"),
+    )
+)]
+async fn newscrobble_post(State(db): State<DatabaseConnection>, RawQuery(query): RawQuery, headers: HeaderMap, body: Bytes) -> Result<Json<V1ScrobbleResult>, V1Error> {
+    let mut params = V1Params::from_query(query)?;
+    params.0.extend(parse_body(&headers, &body)?);
+    scrobble(params, &db).await
+}
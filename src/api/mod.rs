@@ -3,20 +3,28 @@ use axum::{Json, Router};
 use axum::http::header;
 use axum::response::{IntoResponse, Response};
 use serde_json::json;
+use tower_http::normalize_path::NormalizePath;
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
+use crate::database::errors::MalojaError;
+use crate::database::import::{lastfm, maloja, maloja_legacy, scrobbler_log, spotify};
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::server::AppState;
 
-mod audioscrobbler;
-mod listenbrainz;
+pub(crate) mod audioscrobbler;
+mod audioscrobbler_legacy;
+mod auth;
+pub(crate) mod listenbrainz;
 mod maloja_2;
+mod mlj_1;
 
-const APIS: [ScrobbleAPI; 3] = [listenbrainz::API, audioscrobbler::API, maloja_2::API];
+const APIS: [ScrobbleAPI; 5] = [listenbrainz::API, audioscrobbler::API, audioscrobbler_legacy::API, maloja_2::API, mlj_1::API];
 
 pub struct ScrobbleAPI {
     pub prefix: &'static str,
     pub tag: &'static str,
     //endpoints: UtoipaMethodRouter,
-    register: fn(OpenApiRouter) -> OpenApiRouter,
+    register: fn(OpenApiRouter<AppState>) -> OpenApiRouter<AppState>,
 }
 impl ScrobbleAPI {
     //pub fn register_routes(&self) -> OpenApiRouter {
@@ -26,13 +34,33 @@ impl ScrobbleAPI {
     //}
 }
 
+/// Parses a stored raw scrobble with the same rules as when it was submitted
+pub fn parse_raw_scrobble(raw: &RawScrobble) -> Result<ScrobbleWrite, MalojaError> {
+    match raw.source {
+        ScrobbleSource::Listenbrainz => listenbrainz::parse_listen(&raw.payload),
+        ScrobbleSource::Audioscrobbler => audioscrobbler::parse_raw(&raw.payload),
+        ScrobbleSource::AudioscrobblerLegacy => audioscrobbler_legacy::parse_raw(&raw.payload),
+        ScrobbleSource::MalojaV1 => mlj_1::parse_raw(&raw.payload),
+        ScrobbleSource::MalojaV2 => maloja_2::parse_raw(&raw.payload),
+        ScrobbleSource::MalojaExport => maloja::parse_scrobble(&raw.payload),
+        ScrobbleSource::MalojaLegacy => maloja_legacy::parse_row(&raw.payload),
+        ScrobbleSource::LastfmCsv => lastfm::parse_csv_row(&raw.payload),
+        ScrobbleSource::LastfmJson => lastfm::parse_recent_track(&raw.payload),
+        ScrobbleSource::SpotifyExtended => spotify::parse_extended_entry(&raw.payload),
+        ScrobbleSource::SpotifyBasic => spotify::parse_basic_entry(&raw.payload),
+        ScrobbleSource::ScrobblerLog => scrobbler_log::parse_row(&raw.payload),
+    }
+}
+
 #[derive(OpenApi)]
 #[openapi(
     paths(),
     nest(
         (path = listenbrainz::API.prefix, api = listenbrainz::ApiDoc, tags = [listenbrainz::API.tag]),
         (path = audioscrobbler::API.prefix, api = audioscrobbler::ApiDoc, tags = [audioscrobbler::API.tag]),
+        (path = audioscrobbler_legacy::API.prefix, api = audioscrobbler_legacy::ApiDoc, tags = [audioscrobbler_legacy::API.tag]),
         (path = maloja_2::API.prefix, api = maloja_2::ApiDoc, tags = [maloja_2::API.tag]),
+        (path = mlj_1::API.prefix, api = mlj_1::ApiDoc, tags = [mlj_1::API.tag]),
     ),
     servers(
         (url = "/apis")
@@ -45,7 +73,7 @@ impl ScrobbleAPI {
 )]
 pub struct ApiDoc {}
 
-pub fn mount_apis(root_router: Router) -> Router {
+pub fn mount_apis(root_router: Router<AppState>, state: AppState) -> Router<AppState> {
     let mut api_router = OpenApiRouter::new();
     api_router = api_router.route("/openapi.json", get(openapi));
     //let mut api_explorer = SwaggerUi::new("/api_explorer2");
@@ -58,7 +86,8 @@ pub fn mount_apis(root_router: Router) -> Router {
 
     let (api_router_r, _api_router_oapi) = api_router.split_for_parts();
 
-    let root_router = root_router.nest("/apis", api_router_r);
+    // scrobble clients are often configured with a trailing slash
+    let root_router = root_router.nest_service("/apis", NormalizePath::trim_trailing_slash(api_router_r.with_state(state)));
     //root_router = root_router.merge(api_explorer);
     root_router
 }
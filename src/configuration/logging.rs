@@ -1,7 +1,7 @@
 use chrono::Utc;
 use colored::{ColoredString, Colorize};
 use fern;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::configuration::FOLDERS;
 
@@ -39,7 +39,7 @@ pub fn setup_logger() -> Result<(), fern::InitError> {
 }
 
 // Define common colors when various types of info are logged
-pub fn display_path(path: &PathBuf) -> ColoredString {
+pub fn display_path(path: &Path) -> ColoredString {
     ColoredString::from(path.to_string_lossy().to_string()).bright_yellow()
 }
 pub fn display_envvar(var: &str) -> ColoredString {
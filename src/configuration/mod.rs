@@ -1,8 +1,12 @@
 pub mod logging;
 
 use crate::configuration::logging::{display_envvar, display_path};
+use chrono::Weekday;
+use chrono_tz::Tz;
 use confique::{toml, Config};
 use log::warn;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
 use std::fs::{remove_file, File, OpenOptions};
 use std::io::Write;
 use std::net::IpAddr;
@@ -67,6 +71,34 @@ pub static CONFIG: LazyLock<MalojaConfig> = LazyLock::new(|| {
     })
 });
 
+/// The timezone that days, weeks and all displayed times are based on
+pub static TIMEZONE: LazyLock<Tz> = LazyLock::new(|| {
+    if let Some(name) = &CONFIG.timezone {
+        match name.parse() {
+            Ok(timezone) => return timezone,
+            Err(_) => warn!("Unknown timezone {}, using the UTC offset instead", name),
+        }
+    }
+    // the sign in these names is inverted, Etc/GMT-2 is two hours ahead of UTC
+    format!("Etc/GMT{:+}", -(CONFIG.utc_offset as i32)).parse().unwrap_or_else(|_| {
+        warn!("Invalid UTC offset {}, using UTC", CONFIG.utc_offset);
+        Tz::UTC
+    })
+});
+
+pub static WEEK_BEGIN: LazyLock<Weekday> = LazyLock::new(|| {
+    (0..CONFIG.week_offset % 7).fold(Weekday::Sun, |day, _| day.succ())
+});
+
+/// Either the configured admin password, or a random one that is valid until the next restart
+pub static ADMIN_PASSWORD: LazyLock<String> = LazyLock::new(|| {
+    CONFIG.admin_password.clone().unwrap_or_else(|| {
+        let password: String = thread_rng().sample_iter(&Alphanumeric).take(20).map(char::from).collect();
+        warn!("No admin password configured. Generated password for this session: {}", password);
+        password
+    })
+});
+
 pub struct ApplicationFolders {
     pub data: PathBuf,
     pub config: PathBuf,
@@ -108,6 +140,9 @@ pub struct MalojaConfig {
     /// How many scrobbles an album needs in order to be considered Gold status
     #[config(default = 500)]
     pub scrobbles_album_gold: u16,
+    /// Password for administrative API endpoints like key management. If not set, a random one is generated at every start
+    #[config()]
+    pub admin_password: Option<String>,
     /// API Key for Last.fm
     #[config()]
     pub last_fm_api_key: Option<String>,
@@ -126,15 +161,27 @@ pub struct MalojaConfig {
     /// API Key for AudioDB
     #[config()]
     pub audiodb_api_key: Option<String>,
-    /// Offset for Week Start. 0 is Sunday
+    /// First day of the week. 0 is Sunday, 1 is Monday and so on
     #[config(default = 0)]
     pub week_offset: u8,
-    /// Timezone offset, 0 is UTC
+    /// Name of the timezone for all local times, e.g. Europe/Vienna. If not set, utc_offset is used instead
+    #[config()]
+    pub timezone: Option<String>,
+    /// Timezone offset in hours, 0 is UTC. Unlike a timezone, this doesn't account for daylight saving time
     #[config(default = 0)]
-    pub utc_offset: u8,
+    pub utc_offset: i8,
     /// What artist string should be shown for tracks or albums with no artists
     #[config(default = "Various Artists")]
     pub default_albumartist: String,
+    /// Minimum seconds a track must have been played in a Spotify history import to be counted as a scrobble
+    #[config(default = 30)]
+    pub spotify_import_min_listen_seconds: u32,
+    /// Hours between automatic backups of the database, 0 disables them
+    #[config(default = 24)]
+    pub backup_interval_hours: u32,
+    /// How many automatic backups are kept, older ones are deleted. 0 keeps all of them
+    #[config(default = 7)]
+    pub backup_retention: u32,
     /// How to format dates
     #[config(default = "%d. %b %Y %I:%M %p")]
     pub time_format: String,
@@ -145,8 +192,20 @@ fn get_config_file_path() -> PathBuf {
     path
 }
 
+/// Loads the configuration file like at startup, but returns what's wrong with it instead of falling back to defaults
+pub fn check_config() -> Result<PathBuf, confique::Error> {
+    let path = get_config_file_path();
+    MalojaConfig::from_file(&path)?;
+    Ok(path)
+}
+
+/// All options with their descriptions and defaults
+pub fn config_template() -> String {
+    toml::template::<MalojaConfig>(Default::default())
+}
+
 pub fn create_config_template() -> io::Result<()> {
-    let example_config = toml::template::<MalojaConfig>(Default::default());
+    let example_config = config_template();
     let file_path = get_config_file_path();
     if file_path.exists() {
         return Ok(());
@@ -0,0 +1,88 @@
+//! Regular copies of the database, which is the only place the whole listening history is stored
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use chrono::Utc;
+use log::{error, info};
+use sea_orm::DatabaseConnection;
+use tokio::time::sleep;
+use crate::configuration::CONFIG;
+use crate::configuration::logging::display_path;
+use crate::database::{backup_database, backup_folder};
+use crate::database::errors::MalojaError;
+use crate::database::views::BackupInfo;
+
+const SCHEDULED_LABEL: &str = "scheduled";
+/// How long to wait before trying again after a backup failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// All backups in the backup folder, newest first
+pub fn list_backups() -> Result<Vec<BackupInfo>, MalojaError> {
+    let folder = backup_folder();
+    if !folder.exists() {
+        return Ok(vec![]);
+    }
+    let mut backups = vec![];
+    for entry in fs::read_dir(folder)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "sqlite") {
+            backups.push(backup_info(&path)?);
+        }
+    }
+    // names start with the time of the backup
+    backups.sort_by(|a, b| b.file.cmp(&a.file));
+    Ok(backups)
+}
+
+pub fn backup_info(path: &Path) -> Result<BackupInfo, MalojaError> {
+    let metadata = fs::metadata(path)?;
+    let file = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(BackupInfo {
+        scheduled: file.ends_with(&format!("_{}.sqlite", SCHEDULED_LABEL)),
+        file,
+        size: metadata.len(),
+        created: metadata.modified()?.duration_since(UNIX_EPOCH).map(|age| age.as_secs() as i64).unwrap_or_default(),
+    })
+}
+
+/// Backs up the database every `backup_interval_hours` and only keeps the newest `backup_retention` of these backups.
+/// Backups made by hand or before migrations are never deleted
+pub async fn scheduled_backups(db: DatabaseConnection) {
+    let hours = CONFIG.backup_interval_hours;
+    if hours == 0 {
+        info!("Scheduled backups are disabled");
+        return;
+    }
+    let interval = Duration::from_secs(hours as u64 * 3600);
+    loop {
+        // the schedule continues from the last backup, so restarting doesn't cause a new one every time
+        let last = list_backups().ok().and_then(|backups| backups.into_iter().find(|backup| backup.scheduled));
+        if let Some(last) = last {
+            let elapsed = Duration::from_secs((Utc::now().timestamp() - last.created).max(0) as u64);
+            if elapsed < interval {
+                sleep(interval - elapsed).await;
+            }
+        }
+        match scheduled_backup(&db).await {
+            Ok(path) => info!("Database backed up to {}", display_path(&path)),
+            Err(e) => {
+                error!("Scheduled backup failed: {:?}", e);
+                sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn scheduled_backup(db: &DatabaseConnection) -> Result<PathBuf, MalojaError> {
+    let path = backup_database(db, SCHEDULED_LABEL).await?;
+    let retention = CONFIG.backup_retention as usize;
+    if retention > 0 {
+        for backup in list_backups()?.into_iter().filter(|backup| backup.scheduled).skip(retention) {
+            let old = backup_folder().join(&backup.file);
+            fs::remove_file(&old)?;
+            info!("Deleted old backup {}", display_path(&old));
+        }
+    }
+    Ok(path)
+}
@@ -1,8 +1,4 @@
-use axum::http::StatusCode;
-use axum::Json;
-use axum::response::{IntoResponse, Response};
 use serde::Serialize;
-use strum;
 use strum_macros::{Display, EnumString};
 use utoipa::ToSchema;
 
@@ -11,8 +7,14 @@ pub enum MalojaError {
     ArtistNotFound { id: u32 },
     TrackNotFound { id: u32 },
     AlbumNotFound { id: u32 },
+    ApiKeyNotFound { id: u32 },
+    ArtistNameNotFound { name: String },
+    TrackTitleNotFound { title: String },
+    ScrobbleExists { timestamp: i64 },
+    AuthenticationError { message: String },
     DatabaseConnectionError { message: String },
     DatabaseError { message: String },
+    DatabaseVersionTooNew { database: u32, supported: u32 },
     FilesystemError { message: String },
     ParseError { message: String },
 
@@ -37,5 +39,13 @@ impl From<serde_json::Error> for MalojaError {
         MalojaError::ParseError { message: e.to_string() }
     }
 }
-
-
+impl From<csv::Error> for MalojaError {
+    fn from(e: csv::Error) -> Self {
+        MalojaError::ParseError { message: e.to_string() }
+    }
+}
+impl From<zip::result::ZipError> for MalojaError {
+    fn from(e: zip::result::ZipError) -> Self {
+        MalojaError::ParseError { message: e.to_string() }
+    }
+}
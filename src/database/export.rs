@@ -0,0 +1,251 @@
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use log::info;
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use serde_json::json;
+use strum_macros::{Display, EnumString};
+use crate::configuration::FOLDERS;
+use crate::configuration::logging::display_path;
+use crate::database::errors::MalojaError;
+use crate::database::import::maloja::{MalojaExportAlbum, MalojaExportScrobble, MalojaExportTrack};
+use crate::database::repository::scrobble_batch;
+use crate::entity::scrobble::{Model as ScrobbleModel, ScrobbleRead};
+use crate::timeranges::TimeRange;
+
+/// How many scrobbles are read from the database at once
+const EXPORT_BATCH_SIZE: u64 = 1000;
+
+/// Separates several artists within one CSV field
+const LIST_SEPARATOR: &str = "; ";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, EnumString, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportFormat {
+    /// Lossless, can be imported again
+    Maloja,
+    /// With a header row and one column each for what a spreadsheet would need
+    Csv,
+    /// JSON lines with one listen each, as they can be submitted to ListenBrainz
+    Listenbrainz,
+}
+
+impl ExportFormat {
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Maloja => "json",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Listenbrainz => "jsonl",
+        }
+    }
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Maloja => "application/json",
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Listenbrainz => "application/jsonl",
+        }
+    }
+}
+
+pub fn default_export_path(format: ExportFormat) -> PathBuf {
+    let name = match format {
+        // the importer recognizes these by name
+        ExportFormat::Maloja => "maloja_export".to_string(),
+        format => format!("scrobbles_{}", format),
+    };
+    FOLDERS.data.join("export").join(format!("{}_{}.{}", name, Utc::now().format("%Y-%m-%d_%H%M%S"), format.file_extension()))
+}
+
+#[derive(Serialize)]
+struct CsvRow {
+    timestamp: i64,
+    time_local: String,
+    artists: String,
+    title: String,
+    album: Option<String>,
+    album_artists: Option<String>,
+    duration: Option<u32>,
+    origin: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Listen {
+    listened_at: i64,
+    track_metadata: ListenTrackMetadata,
+}
+#[derive(Serialize)]
+struct ListenTrackMetadata {
+    artist_name: String,
+    track_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    release_name: Option<String>,
+    additional_info: ListenAdditionalInfo,
+}
+#[derive(Serialize)]
+struct ListenAdditionalInfo {
+    artist_names: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    release_artist_names: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    submission_client: Option<String>,
+}
+
+fn to_export_scrobble(model: ScrobbleModel, scrobble: ScrobbleRead) -> MalojaExportScrobble {
+    let track = scrobble.track;
+    MalojaExportScrobble {
+        time: scrobble.timestamp,
+        track: MalojaExportTrack {
+            artists: track.artists.into_iter().map(|artist| artist.name).collect(),
+            title: track.title,
+            album: track.album.map(|album| MalojaExportAlbum {
+                artists: Some(album.album_artists.into_iter().map(|artist| artist.name).collect()),
+                albumtitle: album.album_title,
+            }),
+            length: track.track_length,
+        },
+        duration: model.listen_duration,
+        origin: model.origin,
+    }
+}
+
+fn to_csv_row(model: ScrobbleModel, scrobble: ScrobbleRead) -> CsvRow {
+    let track = scrobble.track;
+    let album_artists = track.album.as_ref().map(|album| {
+        album.album_artists.iter().map(|artist| artist.name.as_str()).collect::<Vec<_>>().join(LIST_SEPARATOR)
+    });
+    CsvRow {
+        timestamp: scrobble.timestamp,
+        time_local: scrobble.time_local,
+        artists: track.artists.iter().map(|artist| artist.name.as_str()).collect::<Vec<_>>().join(LIST_SEPARATOR),
+        title: track.title,
+        album: track.album.map(|album| album.album_title),
+        album_artists,
+        duration: model.listen_duration,
+        origin: model.origin,
+    }
+}
+
+fn to_listen(model: ScrobbleModel, scrobble: ScrobbleRead) -> Listen {
+    let track = scrobble.track;
+    let artist_names: Vec<String> = track.artists.into_iter().map(|artist| artist.name).collect();
+    Listen {
+        listened_at: scrobble.timestamp,
+        track_metadata: ListenTrackMetadata {
+            artist_name: artist_names.join(", "),
+            track_name: track.title,
+            release_name: track.album.as_ref().map(|album| album.album_title.clone()),
+            additional_info: ListenAdditionalInfo {
+                artist_names,
+                release_artist_names: track.album.map(|album| album.album_artists.into_iter().map(|artist| artist.name).collect()),
+                duration: track.track_length,
+                submission_client: model.origin,
+            },
+        },
+    }
+}
+
+/// Scrobbles in chronological order, read from the database one batch at a time and handed out as finished chunks
+/// of the export file, so the export never has to be in memory completely
+pub struct Export {
+    format: ExportFormat,
+    timerange: TimeRange,
+    artist_id: Option<u32>,
+    album_id: Option<u32>,
+    track_id: Option<u32>,
+    after: Option<(i64, u32)>,
+    count: u32,
+    started: bool,
+    finished: bool,
+}
+
+impl Export {
+    /// Fails right away if the scrobbles can't be selected like this, rather than with the first chunk
+    pub fn new(format: ExportFormat, timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>, track_id: Option<u32>) -> Result<Self, MalojaError> {
+        if [artist_id, album_id, track_id].iter().filter(|id| id.is_some()).count() > 1 {
+            return Err(MalojaError::ParseError { message: "Scrobbles can only be limited to one artist, album or track".to_string() });
+        }
+        Ok(Export { format, timerange, artist_id, album_id, track_id, after: None, count: 0, started: false, finished: false })
+    }
+
+    pub fn format(&self) -> ExportFormat {
+        self.format
+    }
+
+    /// The next part of the file, or nothing once the export is complete
+    pub async fn next_chunk(&mut self, db: &DatabaseConnection) -> Result<Option<Vec<u8>>, MalojaError> {
+        if self.finished {
+            return Ok(None);
+        }
+        let mut chunk = vec![];
+        if !self.started {
+            self.started = true;
+            if self.format == ExportFormat::Maloja {
+                write!(chunk, "{{\"maloja\":{},\"scrobbles\":[", json!({"export_time": Utc::now().timestamp()}))?;
+            }
+        }
+
+        let batch = scrobble_batch(self.timerange.clone(), self.artist_id, self.album_id, self.track_id, self.after, EXPORT_BATCH_SIZE, db).await?;
+        let Some((last, _)) = batch.last() else {
+            self.finished = true;
+            if self.format == ExportFormat::Maloja {
+                chunk.write_all(b"\n]}\n")?;
+            }
+            return Ok(Some(chunk));
+        };
+        self.after = Some((last.timestamp, last.id));
+
+        match self.format {
+            ExportFormat::Maloja => {
+                for (model, scrobble) in batch {
+                    if self.count > 0 {
+                        chunk.write_all(b",")?;
+                    }
+                    chunk.write_all(b"\n")?;
+                    serde_json::to_writer(&mut chunk, &to_export_scrobble(model, scrobble))?;
+                    self.count += 1;
+                }
+            }
+            ExportFormat::Csv => {
+                // the header only goes before the first row
+                let mut writer = csv::WriterBuilder::new().has_headers(self.count == 0).from_writer(&mut chunk);
+                for (model, scrobble) in batch {
+                    writer.serialize(to_csv_row(model, scrobble))?;
+                    self.count += 1;
+                }
+                writer.flush()?;
+            }
+            ExportFormat::Listenbrainz => {
+                for (model, scrobble) in batch {
+                    serde_json::to_writer(&mut chunk, &to_listen(model, scrobble))?;
+                    chunk.write_all(b"\n")?;
+                    self.count += 1;
+                }
+            }
+        }
+        Ok(Some(chunk))
+    }
+
+    /// Writes the whole export and returns the amount of exported scrobbles
+    pub async fn write_to(mut self, writer: &mut impl Write, db: &DatabaseConnection) -> Result<u32, MalojaError> {
+        while let Some(chunk) = self.next_chunk(db).await? {
+            writer.write_all(&chunk)?;
+        }
+        writer.flush()?;
+        Ok(self.count)
+    }
+}
+
+/// Writes the scrobbles to the file
+pub async fn export_to_file(export: Export, file: &Path, db: &DatabaseConnection) -> Result<u32, MalojaError> {
+    if let Some(folder) = file.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    let mut writer = BufWriter::new(fs::File::create(file)?);
+    let count = export.write_to(&mut writer, db).await?;
+    info!("Exported {} scrobbles to {}", count, display_path(file));
+    Ok(count)
+}
@@ -1,5 +1,6 @@
 use rand::prelude::SliceRandom;
 use rand::{thread_rng, Rng};
+use sea_orm::DatabaseConnection;
 use crate::database;
 use crate::entity::artist::ArtistWrite;
 use crate::entity::scrobble::ScrobbleWrite;
@@ -50,7 +51,7 @@ const SONG_NOUNS_PLURAL: &[&str] = &[
 ];
 
 
-pub async fn fixture() {
+pub async fn fixture(db: &DatabaseConnection) {
 
     const ARTISTS_AMOUNT: u32 = 7;
     const TRACKS_AMOUNT: u32 = 20;
@@ -110,11 +111,12 @@ pub async fn fixture() {
             track: tracks.choose(&mut thread_rng()).unwrap().to_owned(),
             origin: None,
             listen_duration: None,
+            raw_scrobble: None,
         });
 
         timestamp += thread_rng().gen_range(200..2000);
     }
 
-    database::repository::create_scrobbles(scrobbles, false).await.unwrap();
+    database::repository::create_scrobbles(scrobbles, false, db).await.unwrap();
 
 }
\ No newline at end of file
@@ -0,0 +1,181 @@
+//! Scrobble history from Last.fm, either as CSV (as written by the common export tools, with or without header row)
+//! or as the JSON pages returned by `user.getRecentTracks`
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use chrono::NaiveDateTime;
+use csv::StringRecord;
+use serde_json::{Map, Value};
+use crate::database::errors::MalojaError;
+use crate::database::import::RecordSink;
+use crate::entity::album::AlbumWrite;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
+
+const ORIGIN: &str = "import:lastfm";
+
+/// Columns of CSV files without header row, as written by lastfm-to-csv
+const HEADERLESS_COLUMNS: [&str; 4] = ["artist", "album", "track", "date"];
+
+/// Formats of the date columns found in the different exports, always in UTC
+const DATE_FORMATS: [&str; 3] = ["%d %b %Y %H:%M", "%d %b %Y, %H:%M", "%Y-%m-%d %H:%M:%S"];
+
+/// Information that all Last.fm formats have in common
+struct LastfmEntry<'a> {
+    timestamp: i64,
+    artist: &'a str,
+    artist_mbid: Option<&'a str>,
+    track: &'a str,
+    track_mbid: Option<&'a str>,
+    album: Option<&'a str>,
+    album_mbid: Option<&'a str>,
+}
+
+impl LastfmEntry<'_> {
+    fn into_scrobble(self, raw_scrobble: RawScrobble) -> ScrobbleWrite {
+        let artist = ArtistWrite {
+            id: None,
+            name: Some(self.artist.to_string()),
+            mbid: self.artist_mbid.map(String::from),
+            spotify_id: None,
+        };
+        ScrobbleWrite {
+            timestamp: self.timestamp,
+            track: TrackWrite {
+                id: None,
+                title: Some(self.track.to_string()),
+                primary_artists: Some(vec![artist.clone()]),
+                secondary_artists: None,
+                track_length: None,
+                // Last.fm doesn't know album artists, so the track artist is the best guess
+                album: self.album.map(|album| AlbumWrite {
+                    id: None,
+                    album_title: Some(album.to_string()),
+                    album_artists: Some(vec![artist]),
+                    mbid: self.album_mbid.map(String::from),
+                    spotify_id: None,
+                }),
+                mbid: self.track_mbid.map(String::from),
+                spotify_id: None,
+            },
+            origin: Some(ORIGIN.to_string()),
+            listen_duration: None,
+            raw_scrobble: Some(raw_scrobble),
+        }
+    }
+}
+
+fn parse_error(message: &str) -> MalojaError {
+    MalojaError::ParseError { message: message.to_string() }
+}
+
+fn non_empty(value: Option<&Value>) -> Option<&str> {
+    value.and_then(Value::as_str).map(str::trim).filter(|value| !value.is_empty())
+}
+
+fn parse_date(input: &str) -> Result<i64, MalojaError> {
+    DATE_FORMATS.iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .map(|time| time.and_utc().timestamp())
+        .ok_or(MalojaError::ParseError { message: format!("Unknown date format: {}", input) })
+}
+
+fn is_header(record: &StringRecord) -> bool {
+    let fields: Vec<String> = record.iter().map(|field| field.trim().to_lowercase()).collect();
+    fields.iter().any(|field| field == "artist") && fields.iter().any(|field| ["track", "title", "name"].contains(&field.as_str()))
+}
+
+pub fn read_csv(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(file)?;
+
+    let mut columns: Vec<String> = HEADERLESS_COLUMNS.iter().map(|column| column.to_string()).collect();
+    for (index, record) in reader.records().enumerate() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                sink(Err(e.into()));
+                continue;
+            }
+        };
+        if index == 0 && is_header(&record) {
+            columns = record.iter().map(|column| column.trim().to_lowercase()).collect();
+            continue;
+        }
+        // rows are stored by column name, so reparsing doesn't need to know which kind of file they came from
+        let row: Map<String, Value> = columns.iter().zip(record.iter())
+            .map(|(column, value)| (column.clone(), Value::String(value.to_string())))
+            .collect();
+        sink(parse_csv_row(&Value::Object(row)));
+    }
+    Ok(())
+}
+
+/// Parses a CSV row that has been turned into an object of column name and value
+pub fn parse_csv_row(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let field = |keys: &[&str]| keys.iter().find_map(|key| non_empty(payload.get(key)));
+
+    let timestamp = match field(&["uts", "timestamp"]) {
+        Some(uts) => uts.parse().map_err(|_| parse_error("Timestamp must be a number"))?,
+        None => parse_date(field(&["utc_time", "date", "time"]).ok_or(parse_error("Row has no time"))?)?,
+    };
+    let entry = LastfmEntry {
+        timestamp,
+        artist: field(&["artist", "artist_name"]).ok_or(parse_error("Row has no artist"))?,
+        artist_mbid: field(&["artist_mbid"]),
+        track: field(&["track", "title", "name", "track_name"]).ok_or(parse_error("Row has no track"))?,
+        track_mbid: field(&["track_mbid", "mbid"]),
+        album: field(&["album", "album_name"]),
+        album_mbid: field(&["album_mbid"]),
+    };
+    Ok(entry.into_scrobble(RawScrobble::new(ScrobbleSource::LastfmCsv, payload.clone())))
+}
+
+pub fn read_json(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    let document: Value = serde_json::from_reader(BufReader::new(File::open(file)?))?;
+    // either a single page or a list of pages, which is what most backup scripts write
+    let pages = match document {
+        Value::Array(pages) => pages,
+        page => vec![page],
+    };
+
+    for page in pages {
+        let tracks = match page.pointer("/recenttracks/track") {
+            Some(Value::Array(tracks)) => tracks.clone(),
+            // pages with a single track have the object directly
+            Some(track @ Value::Object(_)) => vec![track.clone()],
+            _ => return Err(parse_error("Not a page of recent tracks")),
+        };
+        for track in tracks {
+            // the currently playing track has no date and is not a scrobble yet
+            if non_empty(track.pointer("/@attr/nowplaying")) == Some("true") {
+                continue;
+            }
+            sink(parse_recent_track(&track));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a single track of a `user.getRecentTracks` page
+pub fn parse_recent_track(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    // names are in `#text`, except for the artist in extended pages, which has `name`
+    let text = |key: &str| payload.get(key).and_then(|value| non_empty(value.get("#text")).or(non_empty(value.get("name"))));
+    let mbid = |key: &str| payload.get(key).and_then(|value| non_empty(value.get("mbid")));
+
+    let entry = LastfmEntry {
+        timestamp: non_empty(payload.pointer("/date/uts")).ok_or(parse_error("Track has no date"))?
+            .parse().map_err(|_| parse_error("Timestamp must be a number"))?,
+        artist: text("artist").ok_or(parse_error("Track has no artist"))?,
+        artist_mbid: mbid("artist"),
+        track: non_empty(payload.get("name")).ok_or(parse_error("Track has no name"))?,
+        track_mbid: non_empty(payload.get("mbid")),
+        album: text("album"),
+        album_mbid: mbid("album"),
+    };
+    Ok(entry.into_scrobble(RawScrobble::new(ScrobbleSource::LastfmJson, payload.clone())))
+}
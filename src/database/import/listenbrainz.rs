@@ -0,0 +1,50 @@
+//! Listens from the ListenBrainz user export, either the zip file (with one JSON lines file per month) or
+//! individual JSON lines / JSON array files. Listens are parsed exactly like submitted ones, so they can be reparsed
+//! the same way
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use serde_json::Value;
+use zip::ZipArchive;
+use crate::api::listenbrainz::parse_listen;
+use crate::database::errors::MalojaError;
+use crate::database::import::RecordSink;
+
+/// Whether a file looks like it came from ListenBrainz. Loose JSON lines files are always assumed to be listens
+pub fn is_export(file_name: &str, extension: Option<&str>) -> bool {
+    (file_name.starts_with("listenbrainz_") && matches!(extension, Some("zip" | "json" | "jsonl")))
+        || extension == Some("jsonl")
+}
+
+pub fn read_file(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    match file.extension().and_then(|extension| extension.to_str()) {
+        Some("zip") => {
+            let mut archive = ZipArchive::new(File::open(file)?)?;
+            for index in 0..archive.len() {
+                let entry = archive.by_index(index)?;
+                // the archive also contains the user's feedback and pins, which aren't listens
+                let name = entry.name().to_string();
+                if name.starts_with("listens/") && name.ends_with(".jsonl") {
+                    read_json_lines(BufReader::new(entry), sink)?;
+                }
+                else if name.ends_with("listens.json") {
+                    serde_json::from_reader::<_, Vec<Value>>(entry)?.iter().for_each(|listen| sink(parse_listen(listen)));
+                }
+            }
+        }
+        Some("jsonl") => read_json_lines(BufReader::new(File::open(file)?), sink)?,
+        _ => serde_json::from_reader::<_, Vec<Value>>(BufReader::new(File::open(file)?))?.iter().for_each(|listen| sink(parse_listen(listen))),
+    }
+    Ok(())
+}
+
+fn read_json_lines(reader: impl BufRead, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            sink(serde_json::from_str(&line).map_err(MalojaError::from).and_then(|listen| parse_listen(&listen)));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use std::fmt;
+use std::fs;
+use std::io::BufReader;
+use std::path::Path;
+use serde_json::Value;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use crate::database::errors::MalojaError;
+use crate::database::import::RecordSink;
+use crate::entity::album::AlbumWrite;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
+
+/// The format is shared with the original Maloja, so these are also used to write our own exports
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct MalojaExportScrobble {
+    pub time: i64,
+    pub track: MalojaExportTrack,
+    pub duration: Option<u32>,
+    pub origin: Option<String>
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct MalojaExportTrack {
+    pub artists: Vec<String>,
+    pub title: String,
+    pub album: Option<MalojaExportAlbum>,
+    pub length: Option<u32>,
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct MalojaExportAlbum {
+    pub artists: Option<Vec<String>>,
+    pub albumtitle: String,
+}
+
+/// Walks through the export object and hands every entry of `scrobbles` to the sink while it's being read,
+/// without keeping the whole export in memory
+struct ExportVisitor<'a, 'b> {
+    sink: &'a mut RecordSink<'b>,
+}
+
+impl<'de> Visitor<'de> for ExportVisitor<'_, '_> {
+    type Value = bool;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a Maloja export")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<bool, A::Error> {
+        let mut found_scrobbles = false;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "scrobbles" {
+                map.next_value_seed(ScrobblesSeed { sink: &mut *self.sink })?;
+                found_scrobbles = true;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(found_scrobbles)
+    }
+}
+
+struct ScrobblesSeed<'a, 'b> {
+    sink: &'a mut RecordSink<'b>,
+}
+
+impl<'de> DeserializeSeed<'de> for ScrobblesSeed<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ScrobblesSeed<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of scrobbles")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(scrobble) = seq.next_element::<Value>()? {
+            (self.sink)(parse_scrobble(&scrobble));
+        }
+        Ok(())
+    }
+}
+
+/// Reads the export file of the original Maloja
+pub fn read_file(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(fs::File::open(file)?));
+    let found_scrobbles = deserializer.deserialize_map(ExportVisitor { sink })?;
+    deserializer.end()?;
+    if !found_scrobbles {
+        return Err(MalojaError::ParseError { message: "Export contains no scrobbles".to_string() });
+    }
+    Ok(())
+}
+
+/// Parses a single scrobble of a Maloja export, also used when reparsing stored raw scrobbles
+pub fn parse_scrobble(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let scrobble: MalojaExportScrobble = serde_json::from_value(payload.clone())?;
+    Ok(ScrobbleWrite {
+        timestamp: scrobble.time,
+        track: TrackWrite {
+            id: None,
+            title: Some(scrobble.track.title),
+            primary_artists: Some(scrobble.track.artists.into_iter().map(|a| {
+                ArtistWrite {
+                    id: None,
+                    name: Some(a),
+                    mbid: None,
+                    spotify_id: None,
+                }
+            }).collect()),
+            album: scrobble.track.album.map(|al| AlbumWrite {
+                    id: None,
+                    album_title: Some(al.albumtitle),
+                    album_artists: al.artists.map(|aas| {
+                        aas.iter().map(|aa| {
+                            // outer map is to unwrap the option, inner map an actual vector map
+                            ArtistWrite {
+                                id: None,
+                                name: Some(aa.to_owned()),
+                                mbid: None,
+                                spotify_id: None,
+                            }
+                        }).collect()
+                    }),
+                    mbid: None,
+                    spotify_id: None,
+                }) ,
+            secondary_artists: None,
+            track_length: scrobble.track.length,
+            mbid: None,
+            spotify_id: None,
+        },
+        origin: scrobble.origin,
+        listen_duration: scrobble.duration,
+        raw_scrobble: Some(RawScrobble::new(ScrobbleSource::MalojaExport, payload.clone())),
+    })
+}
@@ -0,0 +1,122 @@
+//! Scrobbles of Maloja versions before the SQLite database, which were stored as one TSV file per month in
+//! `scrobbles/`. These can be imported as loose files, as the whole `scrobbles` folder or as a backup archive
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use flate2::read::GzDecoder;
+use serde_json::{Map, Value};
+use tar::Archive;
+use crate::database::errors::MalojaError;
+use crate::database::import::RecordSink;
+use crate::entity::album::AlbumWrite;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
+
+const COLUMNS: [&str; 5] = ["time", "artists", "title", "album", "duration"];
+/// Maloja used the unit separator symbol between artists, since it doesn't appear in actual names
+const ARTIST_SEPARATOR: char = '␟';
+
+pub fn is_backup(file_name: &str) -> bool {
+    file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz")
+}
+
+fn parse_error(message: &str) -> MalojaError {
+    MalojaError::ParseError { message: message.to_string() }
+}
+
+/// Reads a single TSV file, or all TSV files of a `scrobbles` folder
+pub fn read_path(path: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    if !path.is_dir() {
+        return read_tsv(BufReader::new(fs::File::open(path)?), sink);
+    }
+    for entry in fs::read_dir(path)? {
+        let file = entry?.path();
+        if file.extension().is_some_and(|extension| extension == "tsv") {
+            read_tsv(BufReader::new(fs::File::open(&file)?), sink)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads all scrobble files of a backup archive
+pub fn read_backup(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    let mut archive = Archive::new(GzDecoder::new(fs::File::open(file)?));
+    let mut found_tsv = false;
+    let mut found_database = false;
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.into_owned();
+        let in_scrobbles = path.parent().is_some_and(|parent| parent.ends_with("scrobbles"));
+        if in_scrobbles && path.extension().is_some_and(|extension| extension == "tsv") {
+            read_tsv(BufReader::new(entry), sink)?;
+            found_tsv = true;
+        }
+        else if path.extension().is_some_and(|extension| extension == "sqlite") {
+            found_database = true;
+        }
+    }
+    if !found_tsv && found_database {
+        return Err(parse_error("Backups of Maloja 3 contain a database, please import the maloja_export.json of that installation instead"));
+    }
+    Ok(())
+}
+
+fn read_tsv(reader: impl BufRead, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let row: Map<String, Value> = COLUMNS.iter().zip(line.split('\t'))
+            .map(|(column, value)| (column.to_string(), Value::String(value.to_string())))
+            .collect();
+        sink(parse_row(&Value::Object(row)));
+    }
+    Ok(())
+}
+
+/// Parses a line of a scrobble file that has been turned into an object of column name and value
+pub fn parse_row(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let field = |key: &str| payload.get(key).and_then(Value::as_str).map(str::trim).filter(|value| !value.is_empty());
+
+    let artists: Vec<ArtistWrite> = field("artists").ok_or(parse_error("Line has no artists"))?
+        .split(ARTIST_SEPARATOR)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| ArtistWrite {
+            id: None,
+            name: Some(name.to_string()),
+            mbid: None,
+            spotify_id: None,
+        })
+        .collect();
+    if artists.is_empty() {
+        return Err(parse_error("Line has no artists"));
+    }
+    Ok(ScrobbleWrite {
+        timestamp: field("time").ok_or(parse_error("Line has no time"))?
+            .parse().map_err(|_| parse_error("Time must be a number"))?,
+        track: TrackWrite {
+            id: None,
+            title: Some(field("title").ok_or(parse_error("Line has no title"))?.to_string()),
+            // these versions had no concept of album artists
+            album: field("album").map(|album| AlbumWrite {
+                id: None,
+                album_title: Some(album.to_string()),
+                album_artists: Some(artists.clone()),
+                mbid: None,
+                spotify_id: None,
+            }),
+            primary_artists: Some(artists),
+            secondary_artists: None,
+            track_length: None,
+            mbid: None,
+            spotify_id: None,
+        },
+        origin: None,
+        listen_duration: field("duration").and_then(|duration| duration.parse().ok()),
+        raw_scrobble: Some(RawScrobble::new(ScrobbleSource::MalojaLegacy, payload.clone())),
+    })
+}
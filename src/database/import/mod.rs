@@ -0,0 +1,229 @@
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use chrono::Utc;
+use log::{error, info, warn};
+use sea_orm::DatabaseConnection;
+use tokio::sync::mpsc;
+use crate::configuration::FOLDERS;
+use crate::configuration::logging::display_path;
+use crate::database::errors::MalojaError;
+use crate::database::repository::create_scrobbles_counted;
+use crate::database::views::{ImportReport, ImportState};
+use crate::entity::scrobble::ScrobbleWrite;
+
+pub mod lastfm;
+pub mod listenbrainz;
+pub mod maloja;
+pub mod maloja_legacy;
+pub mod scrobbler_log;
+pub mod spotify;
+
+/// How many scrobbles are written to the database at once
+const IMPORT_BATCH_SIZE: usize = 1000;
+/// How many parsed records may wait for the database before parsing pauses
+const CHANNEL_CAPACITY: usize = 2 * IMPORT_BATCH_SIZE;
+/// After how many records the progress is logged
+const PROGRESS_LOG_INTERVAL: u32 = 10_000;
+/// Distinct rejection reasons kept per import, so a file with a different error in every line doesn't flood the report
+const MAX_REJECTION_REASONS: usize = 100;
+
+/// Receives every record of a file as soon as it has been parsed, or the reason it was rejected
+pub type RecordSink<'a> = dyn FnMut(Result<ScrobbleWrite, MalojaError>) + 'a;
+/// Reads a whole file and hands its records to the sink. Only fails if the file as a whole can't be read
+pub type Parser = fn(&Path, &mut RecordSink) -> Result<(), MalojaError>;
+
+/// Reports of all imports since the start, including the ones still running
+static IMPORTS: LazyLock<Mutex<Vec<ImportReport>>> = LazyLock::new(|| Mutex::new(vec![]));
+
+pub fn import_status() -> Vec<ImportReport> {
+    IMPORTS.lock().expect("Import status is never poisoned").clone()
+}
+
+pub async fn import(db: DatabaseConnection) -> Result<(i32, i32), io::Error> {
+    let import_folder = FOLDERS.data.join("import");
+    let done_folder = import_folder.join("done");
+
+    let (mut imported, mut failed): (i32, i32) = (0, 0);
+    if import_folder.exists() {
+        for entry in fs::read_dir(&import_folder)? {
+            let path = entry?.path();
+            if path == done_folder {
+                continue;
+            }
+            let Some((format, parser)) = detect_format(&path) else {
+                error!("Could not import {}: Unknown file format", display_path(&path));
+                failed += 1;
+                continue;
+            };
+            let report = import_file(&path, format, parser, &db).await;
+            if let Err(e) = write_report(&report, &done_folder) {
+                error!("Could not write import report for {}: {}", display_path(&path), describe(&e));
+            }
+            match report.state {
+                ImportState::Finished => {
+                    imported += 1;
+                    // so it isn't imported again at the next start
+                    if let Err(e) = move_to_done(&path, &done_folder) {
+                        error!("Could not move {} to {}: {}", display_path(&path), display_path(&done_folder), e);
+                    }
+                }
+                _ => failed += 1,
+            }
+        }
+    }
+    Ok((imported, failed))
+}
+
+/// Imports a single file or folder from anywhere, without moving it afterwards
+pub async fn import_path(path: &Path, db: &DatabaseConnection) -> Result<ImportReport, MalojaError> {
+    if !path.exists() {
+        return Err(MalojaError::FilesystemError { message: format!("{} does not exist", path.display()) });
+    }
+    let (format, parser) = detect_format(path).ok_or(MalojaError::ParseError { message: "Unknown file format".to_string() })?;
+    Ok(import_file(path, format, parser, db).await)
+}
+
+fn detect_format(file: &Path) -> Option<(&'static str, Parser)> {
+    let file_name = file.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let extension = file.extension().and_then(|extension| extension.to_str()).map(str::to_lowercase);
+
+    match (file_name, extension.as_deref()) {
+        (name, Some("json")) if name.starts_with("maloja_export") => Some(("Maloja export", maloja::read_file)),
+        ("scrobbles", _) | (_, Some("tsv")) => Some(("legacy Maloja scrobbles", maloja_legacy::read_path)),
+        (name, _) if maloja_legacy::is_backup(name) => Some(("Maloja backup", maloja_legacy::read_backup)),
+        (name, _) if spotify::is_extended_history(name) => Some(("Spotify extended streaming history", spotify::read_extended_history)),
+        (name, _) if spotify::is_basic_history(name) => Some(("Spotify streaming history", spotify::read_basic_history)),
+        (name, _) if scrobbler_log::is_scrobbler_log(name) => Some(("portable player log", scrobbler_log::read_file)),
+        (name, extension) if listenbrainz::is_export(name, extension) => Some(("ListenBrainz export", listenbrainz::read_file)),
+        (_, Some("csv")) => Some(("Last.fm CSV export", lastfm::read_csv)),
+        (_, Some("json")) => Some(("Last.fm JSON export", lastfm::read_json)),
+        _ => None,
+    }
+}
+
+fn describe(e: &MalojaError) -> String {
+    match e {
+        MalojaError::ParseError { message } | MalojaError::FilesystemError { message } | MalojaError::DatabaseError { message } => message.clone(),
+        e => format!("{:?}", e),
+    }
+}
+
+/// Parses the file on a blocking thread and writes its scrobbles in batches while it's still being read,
+/// so that large exports never have to be held in memory completely
+pub(crate) async fn import_file(file: &Path, format: &str, parser: Parser, db: &DatabaseConnection) -> ImportReport {
+    let mut report = ImportReport {
+        file: file.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+        format: format.to_string(),
+        started: Utc::now().timestamp(),
+        ..ImportReport::default()
+    };
+    info!("Importing from {} {}. This could take a while...", format, display_path(file));
+    let index = {
+        let mut imports = IMPORTS.lock().expect("Import status is never poisoned");
+        imports.push(report.clone());
+        imports.len() - 1
+    };
+    let update = |report: &ImportReport| {
+        IMPORTS.lock().expect("Import status is never poisoned")[index] = report.clone();
+    };
+
+    let (sender, mut receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let path = file.to_path_buf();
+    let reader = tokio::task::spawn_blocking(move || {
+        // if the receiver is gone, nobody is waiting for the rest of the file anymore
+        parser(&path, &mut |record| { let _ = sender.blocking_send(record); })
+    });
+
+    let mut batch: Vec<ScrobbleWrite> = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    loop {
+        let record = receiver.recv().await;
+        let finished = record.is_none();
+        match record {
+            Some(Ok(scrobble)) => {
+                report.parsed += 1;
+                batch.push(scrobble);
+            }
+            Some(Err(e)) => reject(&mut report, &e),
+            None => {}
+        }
+        if batch.len() >= IMPORT_BATCH_SIZE || (finished && !batch.is_empty()) {
+            write_batch(std::mem::take(&mut batch), &mut report, db).await;
+            update(&report);
+        }
+        if finished {
+            break;
+        }
+        let total = report.parsed + report.rejected;
+        if total % PROGRESS_LOG_INTERVAL == 0 {
+            info!("{}: {} records read, {} scrobbles created, {} duplicates, {} rejected",
+                display_path(file), total, report.created, report.duplicate, report.rejected);
+        }
+    }
+    let result = reader.await.unwrap_or_else(|e| Err(MalojaError::ParseError { message: e.to_string() }));
+
+    report.finished = Some(Utc::now().timestamp());
+    match &result {
+        Ok(()) => {
+            report.state = ImportState::Finished;
+            info!("Imported {}: {} scrobbles created, {} duplicates, {} rejected",
+                display_path(file), report.created, report.duplicate, report.rejected);
+        }
+        Err(e) => {
+            report.state = ImportState::Failed;
+            report.error = Some(describe(e));
+            error!("Could not import {}: {}", display_path(file), describe(e));
+        }
+    }
+    update(&report);
+    report
+}
+
+fn reject(report: &mut ImportReport, e: &MalojaError) {
+    report.rejected += 1;
+    let mut reason = describe(e);
+    if report.rejection_reasons.len() >= MAX_REJECTION_REASONS && !report.rejection_reasons.contains_key(&reason) {
+        reason = "Other reasons".to_string();
+    }
+    *report.rejection_reasons.entry(reason).or_default() += 1;
+}
+
+/// If the batch fails as a whole, its scrobbles are written one at a time,
+/// so that a bad record is only rejected itself instead of failing the import
+async fn write_batch(batch: Vec<ScrobbleWrite>, report: &mut ImportReport, db: &DatabaseConnection) {
+    let size = batch.len();
+    match create_scrobbles_counted(batch.clone(), false, db).await {
+        Ok((_, created)) => {
+            report.created += created as u32;
+            report.duplicate += (size - created) as u32;
+        }
+        Err(e) => {
+            warn!("Could not write a batch of {} scrobbles, retrying them one at a time: {}", size, describe(&e));
+            for scrobble in batch {
+                match create_scrobbles_counted(vec![scrobble], false, db).await {
+                    Ok((_, 0)) => report.duplicate += 1,
+                    Ok(_) => report.created += 1,
+                    Err(e) => reject(report, &e),
+                }
+            }
+        }
+    }
+}
+
+/// Every import leaves a report, also the failed ones
+fn write_report(report: &ImportReport, done_folder: &Path) -> Result<(), MalojaError> {
+    fs::create_dir_all(done_folder)?;
+    let file = fs::File::create(done_folder.join(format!("{}.report.json", report.file)))?;
+    serde_json::to_writer_pretty(file, report)?;
+    Ok(())
+}
+
+fn move_to_done(file: &Path, done_folder: &Path) -> io::Result<()> {
+    let name = file.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let mut target: PathBuf = done_folder.join(&name);
+    // don't overwrite a file of the same name that was imported earlier
+    if target.exists() {
+        target = done_folder.join(format!("{}_{}", Utc::now().timestamp(), name));
+    }
+    fs::rename(file, target)
+}
@@ -0,0 +1,108 @@
+//! The `.scrobbler.log` written by Rockbox and other portable players, as specified by the Audioscrobbler
+//! portable device format. Each line is one tab-separated track, preceded by `#` header lines
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use chrono::{DateTime, TimeZone};
+use serde_json::{Map, Value};
+use crate::configuration::TIMEZONE;
+use crate::database::errors::MalojaError;
+use crate::database::import::RecordSink;
+use crate::entity::album::AlbumWrite;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
+
+const ORIGIN: &str = "import:scrobbler_log";
+const COLUMNS: [&str; 8] = ["artist", "album", "title", "tracknum", "length", "rating", "timestamp", "mbid"];
+
+pub fn is_scrobbler_log(file_name: &str) -> bool {
+    file_name.ends_with("scrobbler.log")
+}
+
+fn parse_error(message: &str) -> MalojaError {
+    MalojaError::ParseError { message: message.to_string() }
+}
+
+pub fn read_file(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    let mut lines = BufReader::new(File::open(file)?).lines();
+    if !lines.next().transpose()?.is_some_and(|header| header.starts_with("#AUDIOSCROBBLER/")) {
+        return Err(parse_error("Not a scrobbler log"));
+    }
+
+    let mut timezone_unknown = false;
+    let mut client: Option<String> = None;
+    for line in lines {
+        let line = line?;
+        if let Some(header) = line.strip_prefix('#') {
+            match header.split_once('/') {
+                Some(("TZ", timezone)) => timezone_unknown = timezone.trim() == "UNKNOWN",
+                Some(("CLIENT", name)) => client = Some(name.trim().to_string()),
+                _ => {}
+            }
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        // header information is stored with the row, so reparsing doesn't need the rest of the file
+        let mut row: Map<String, Value> = COLUMNS.iter().zip(line.split('\t'))
+            .map(|(column, value)| (column.to_string(), Value::String(value.to_string())))
+            .collect();
+        row.insert("timezone_unknown".to_string(), Value::Bool(timezone_unknown));
+        if let Some(client) = &client {
+            row.insert("client".to_string(), Value::String(client.clone()));
+        }
+        if row.get("rating").and_then(Value::as_str) == Some("S") {
+            sink(Err(parse_error("Skipped on the device")));
+            continue;
+        }
+        sink(parse_row(&Value::Object(row)));
+    }
+    Ok(())
+}
+
+/// Parses a line of the log that has been turned into an object of column name and value
+pub fn parse_row(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let field = |key: &str| payload.get(key).and_then(Value::as_str).map(str::trim).filter(|value| !value.is_empty());
+
+    let mut timestamp: i64 = field("timestamp").ok_or(parse_error("Line has no timestamp"))?
+        .parse().map_err(|_| parse_error("Timestamp must be a number"))?;
+    // devices without a clock setting log their local time as if it was UTC
+    if payload.get("timezone_unknown").and_then(Value::as_bool).unwrap_or_default() {
+        let local = DateTime::from_timestamp(timestamp, 0).ok_or(parse_error("Timestamp out of range"))?.naive_utc();
+        timestamp = TIMEZONE.from_local_datetime(&local).earliest()
+            .ok_or(parse_error("Time doesn't exist in the configured timezone"))?
+            .timestamp();
+    }
+
+    let artist = ArtistWrite {
+        id: None,
+        name: Some(field("artist").ok_or(parse_error("Line has no artist"))?.to_string()),
+        mbid: None,
+        spotify_id: None,
+    };
+    Ok(ScrobbleWrite {
+        timestamp,
+        track: TrackWrite {
+            id: None,
+            title: Some(field("title").ok_or(parse_error("Line has no title"))?.to_string()),
+            primary_artists: Some(vec![artist.clone()]),
+            secondary_artists: None,
+            track_length: field("length").and_then(|length| length.parse().ok()),
+            album: field("album").map(|album| AlbumWrite {
+                id: None,
+                album_title: Some(album.to_string()),
+                album_artists: Some(vec![artist]),
+                mbid: None,
+                spotify_id: None,
+            }),
+            mbid: field("mbid").map(String::from),
+            spotify_id: None,
+        },
+        origin: Some(field("client").map(|client| format!("{}:{}", ORIGIN, client)).unwrap_or(ORIGIN.to_string())),
+        listen_duration: None,
+        raw_scrobble: Some(RawScrobble::new(ScrobbleSource::ScrobblerLog, payload.clone())),
+    })
+}
@@ -0,0 +1,154 @@
+//! Streaming history from the Spotify privacy data download, either the extended history
+//! (`Streaming_History_Audio_*.json`, formerly `endsong_*.json`) or the basic one (`StreamingHistory*.json`)
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use chrono::{DateTime, NaiveDateTime};
+use serde::Deserialize;
+use serde_json::Value;
+use crate::configuration::CONFIG;
+use crate::database::errors::MalojaError;
+use crate::database::import::RecordSink;
+use crate::entity::album::AlbumWrite;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::{RawScrobble, ScrobbleSource, ScrobbleWrite};
+use crate::entity::track::TrackWrite;
+
+const ORIGIN: &str = "import:spotify";
+
+#[derive(Deserialize, Debug, Clone)]
+struct ExtendedEntry {
+    ts: String,
+    ms_played: u64,
+    master_metadata_track_name: Option<String>,
+    master_metadata_album_artist_name: Option<String>,
+    master_metadata_album_album_name: Option<String>,
+    spotify_track_uri: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct BasicEntry {
+    end_time: String,
+    artist_name: String,
+    track_name: String,
+    ms_played: u64,
+}
+
+/// Whether a file of the data download is one of the streaming histories (and not e.g. the podcast or video one)
+pub fn is_extended_history(file_name: &str) -> bool {
+    (file_name.starts_with("Streaming_History_Audio_") || file_name.starts_with("endsong_")) && file_name.ends_with(".json")
+}
+pub fn is_basic_history(file_name: &str) -> bool {
+    file_name.starts_with("StreamingHistory") && file_name.ends_with(".json")
+}
+
+fn parse_error(message: &str) -> MalojaError {
+    MalojaError::ParseError { message: message.to_string() }
+}
+
+/// Spotify timestamps mark the end of playback, scrobbles are timed by their start
+fn start_timestamp(end: i64, ms_played: u64) -> i64 {
+    end - (ms_played / 1000) as i64
+}
+
+/// Spotify only has the track URI, artists and albums are identified by name
+fn scrobble(timestamp: i64, ms_played: u64, artist: String, track: String, album: Option<String>, spotify_id: Option<String>, raw_scrobble: RawScrobble) -> ScrobbleWrite {
+    let artist = ArtistWrite {
+        id: None,
+        name: Some(artist),
+        mbid: None,
+        spotify_id: None,
+    };
+    ScrobbleWrite {
+        timestamp,
+        track: TrackWrite {
+            id: None,
+            title: Some(track),
+            primary_artists: Some(vec![artist.clone()]),
+            secondary_artists: None,
+            track_length: None,
+            // the 'album artist' of the export is really just the first track artist
+            album: album.map(|album| AlbumWrite {
+                id: None,
+                album_title: Some(album),
+                album_artists: Some(vec![artist]),
+                mbid: None,
+                spotify_id: None,
+            }),
+            mbid: None,
+            spotify_id,
+        },
+        origin: Some(ORIGIN.to_string()),
+        listen_duration: Some((ms_played / 1000) as u32),
+        raw_scrobble: Some(raw_scrobble),
+    }
+}
+
+pub fn read_extended_history(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    read_file(file, parse_extended_entry, sink)
+}
+pub fn read_basic_history(file: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    read_file(file, parse_basic_entry, sink)
+}
+
+/// Plays that were too short to count as a scrobble are rejected
+fn read_file(file: &Path, parse: fn(&Value) -> Result<ScrobbleWrite, MalojaError>, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    let entries: Vec<Value> = serde_json::from_reader(BufReader::new(File::open(file)?))?;
+    let min_duration = CONFIG.spotify_import_min_listen_seconds;
+
+    for entry in entries {
+        // podcast episodes are in the same file, but have no track name
+        if entry.get("master_metadata_track_name").is_some_and(Value::is_null) {
+            sink(Err(parse_error("Not a music track")));
+            continue;
+        }
+        sink(parse(&entry).and_then(|scrobble| match scrobble.listen_duration {
+            Some(duration) if duration < min_duration => Err(MalojaError::ParseError {
+                message: format!("Played for less than {} seconds", min_duration),
+            }),
+            _ => Ok(scrobble),
+        }));
+    }
+    Ok(())
+}
+
+/// Parses a single entry of the extended streaming history
+pub fn parse_extended_entry(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let entry: ExtendedEntry = serde_json::from_value(payload.clone())?;
+    let end = DateTime::parse_from_rfc3339(&entry.ts)
+        .map_err(|_| MalojaError::ParseError { message: format!("Unknown date format: {}", entry.ts) })?
+        .timestamp();
+
+    Ok(scrobble(
+        start_timestamp(end, entry.ms_played),
+        entry.ms_played,
+        entry.master_metadata_album_artist_name.ok_or(parse_error("Entry has no artist"))?,
+        entry.master_metadata_track_name.ok_or(parse_error("Entry has no track"))?,
+        entry.master_metadata_album_album_name.filter(|album| !album.is_empty()),
+        // spotify:track:<id>
+        entry.spotify_track_uri.and_then(|uri| uri.rsplit(':').next().map(String::from)),
+        RawScrobble::new(ScrobbleSource::SpotifyExtended, payload.clone()),
+    ))
+}
+
+/// Parses a single entry of the basic streaming history, which has neither albums nor track URIs
+pub fn parse_basic_entry(payload: &Value) -> Result<ScrobbleWrite, MalojaError> {
+    let entry: BasicEntry = serde_json::from_value(payload.clone())?;
+    // always UTC, despite not saying so
+    let end = NaiveDateTime::parse_from_str(&entry.end_time, "%Y-%m-%d %H:%M")
+        .map_err(|_| MalojaError::ParseError { message: format!("Unknown date format: {}", entry.end_time) })?
+        .and_utc()
+        .timestamp();
+
+    Ok(scrobble(
+        start_timestamp(end, entry.ms_played),
+        entry.ms_played,
+        entry.artist_name,
+        entry.track_name,
+        None,
+        None,
+        RawScrobble::new(ScrobbleSource::SpotifyBasic, payload.clone()),
+    ))
+}
@@ -0,0 +1,120 @@
+//! Versioned schema changes. Each migration is applied exactly once and recorded in the `schema_version` table.
+//! Released migrations must never be changed, any further change to the schema needs a new migration at the end
+//! of the list. The statements are plain SQL instead of being derived from the entities, so that they stay the same
+//! when the entities change
+
+use log::info;
+use sea_orm::{ConnectionTrait, DbBackend, DbConn, Statement, TransactionTrait};
+use crate::configuration::logging::display_path;
+use crate::database::backup_database;
+use crate::database::errors::MalojaError;
+
+struct Migration {
+    version: u32,
+    description: &'static str,
+    statements: &'static [&'static str],
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        // databases from before versioning already have some of these tables, so they are created only if missing
+        statements: &[
+            r#"CREATE TABLE IF NOT EXISTS "scrobbles" ( "timestamp" bigint NOT NULL PRIMARY KEY, "track_id" integer NOT NULL, "raw_scrobble" json_text NOT NULL, "origin" varchar, "listen_duration" integer, FOREIGN KEY ("track_id") REFERENCES "tracks" ("id") )"#,
+            r#"CREATE TABLE IF NOT EXISTS "tracks" ( "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT, "title" varchar NOT NULL, "title_normalized" varchar NOT NULL, "track_length" integer, "album_id" integer, "mbid" varchar UNIQUE, "spotify_id" varchar UNIQUE, FOREIGN KEY ("album_id") REFERENCES "albums" ("id") )"#,
+            r#"CREATE TABLE IF NOT EXISTS "artists" ( "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT, "name" varchar NOT NULL, "name_normalized" varchar NOT NULL UNIQUE, "mbid" varchar UNIQUE, "spotify_id" varchar UNIQUE )"#,
+            r#"CREATE TABLE IF NOT EXISTS "albums" ( "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT, "album_title" varchar NOT NULL, "album_title_normalized" varchar NOT NULL, "mbid" varchar UNIQUE, "spotify_id" varchar UNIQUE )"#,
+            r#"CREATE TABLE IF NOT EXISTS "track_artists" ( "track_id" integer NOT NULL, "artist_id" integer NOT NULL, "primary" boolean NOT NULL, "artist_alias" varchar, CONSTRAINT "pk-track_artists" PRIMARY KEY ("track_id", "artist_id"), FOREIGN KEY ("track_id") REFERENCES "tracks" ("id"), FOREIGN KEY ("artist_id") REFERENCES "artists" ("id") )"#,
+            r#"CREATE TABLE IF NOT EXISTS "album_artists" ( "album_id" integer NOT NULL, "artist_id" integer NOT NULL, CONSTRAINT "pk-album_artists" PRIMARY KEY ("album_id", "artist_id"), FOREIGN KEY ("album_id") REFERENCES "albums" ("id"), FOREIGN KEY ("artist_id") REFERENCES "artists" ("id") )"#,
+            r#"CREATE TABLE IF NOT EXISTS "api_keys" ( "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT, "key" varchar NOT NULL UNIQUE, "secret" varchar NOT NULL, "name" varchar NOT NULL, "description" varchar, "created" bigint NOT NULL )"#,
+            r#"CREATE TABLE IF NOT EXISTS "sessions" ( "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT, "session_key" varchar NOT NULL UNIQUE, "api_key_id" integer NOT NULL, "created" bigint NOT NULL, FOREIGN KEY ("api_key_id") REFERENCES "api_keys" ("id") )"#,
+        ],
+    },
+    Migration {
+        version: 2,
+        description: "Scrobble IDs instead of timestamp as primary key",
+        // sqlite can't change the primary key of a table, so it is rebuilt. ordered by timestamp, so the IDs of
+        // existing scrobbles are chronological
+        statements: &[
+            r#"CREATE TABLE "scrobbles_new" ( "id" integer NOT NULL PRIMARY KEY AUTOINCREMENT, "timestamp" bigint NOT NULL, "track_id" integer NOT NULL, "raw_scrobble" json_text NOT NULL, "origin" varchar, "listen_duration" integer, FOREIGN KEY ("track_id") REFERENCES "tracks" ("id") )"#,
+            r#"INSERT INTO "scrobbles_new" ("timestamp", "track_id", "raw_scrobble", "origin", "listen_duration") SELECT "timestamp", "track_id", "raw_scrobble", "origin", "listen_duration" FROM "scrobbles" ORDER BY "timestamp""#,
+            r#"DROP TABLE "scrobbles""#,
+            r#"ALTER TABLE "scrobbles_new" RENAME TO "scrobbles""#,
+            r#"CREATE UNIQUE INDEX "idx-scrobbles-timestamp-track_id" ON "scrobbles" ("timestamp", "track_id")"#,
+        ],
+    },
+];
+
+/// Schema version this build of Maloja expects
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Schema version of the database, 0 if it has never been migrated
+pub async fn current_version(db: &DbConn) -> Result<u32, MalojaError> {
+    let result = db.query_one(Statement::from_string(
+        DbBackend::Sqlite,
+        "SELECT MAX(version) AS version FROM schema_version",
+    )).await?;
+    let version: Option<u32> = match result {
+        Some(row) => row.try_get("", "version")?,
+        None => None,
+    };
+    Ok(version.unwrap_or(0))
+}
+
+/// Brings the database to the latest schema version. If there is anything to migrate in an existing database,
+/// a backup is created first. Databases from a newer version of Maloja are refused instead of touched
+pub async fn migrate(db: &DbConn) -> Result<(), MalojaError> {
+    db.execute_unprepared(
+        r#"CREATE TABLE IF NOT EXISTS "schema_version" ( "version" integer NOT NULL PRIMARY KEY, "description" varchar NOT NULL, "applied" bigint NOT NULL )"#
+    ).await?;
+
+    let current = current_version(db).await?;
+    let latest = latest_version();
+    if current > latest {
+        return Err(MalojaError::DatabaseVersionTooNew { database: current, supported: latest });
+    }
+
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > current).collect();
+    if pending.is_empty() {
+        info!("Database schema is up to date (version {})", current);
+        return Ok(());
+    }
+
+    if current > 0 || has_tables(db).await? {
+        let path = backup_database(db, &format!("pre_migration_v{}", current)).await?;
+        info!("Backed up database to {} before migrating", display_path(&path));
+    }
+
+    for migration in pending {
+        info!("Migrating database to version {}: {}", migration.version, migration.description);
+        // sqlite can roll back schema changes, so a failed migration leaves the database at the previous version
+        let txn = db.begin().await?;
+        for statement in migration.statements {
+            txn.execute_unprepared(statement).await?;
+        }
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"INSERT INTO "schema_version" ("version", "description", "applied") VALUES (?, ?, ?)"#,
+            [migration.version.into(), migration.description.into(), chrono::Utc::now().timestamp().into()],
+        )).await?;
+        txn.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Whether the database has content from before versioning, which is worth a backup
+async fn has_tables(db: &DbConn) -> Result<bool, MalojaError> {
+    let result = db.query_one(Statement::from_string(
+        DbBackend::Sqlite,
+        "SELECT COUNT(*) AS amount FROM sqlite_master WHERE type = 'table' AND name = 'scrobbles'",
+    )).await?;
+    let amount: i64 = match result {
+        Some(row) => row.try_get("", "amount")?,
+        None => 0,
+    };
+    Ok(amount > 0)
+}
@@ -1,20 +1,24 @@
 
 pub mod views;
 pub mod import;
+pub mod export;
 pub mod repository;
 pub mod errors;
-
-use std::io::Error;
+pub mod migrations;
+pub mod backups;
+pub mod fixtures;
+
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
 use crate::configuration::FOLDERS;
-use crate::entity::{
-    album::Entity as Album,
-    artist::Entity as Artist,
-    scrobble::Entity as Scrobble,
-    track::Entity as Track,
-    track_artist::Entity as TrackArtist,
-    album_artist::Entity as AlbumArtist,
-};
-use sea_orm::{ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbConn, DbErr, Schema, Statement};
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, DbConn, Statement};
+use sea_orm::sqlx::ConnectOptions;
+use sea_orm::sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous};
 use std::path::PathBuf;
 use crate::database::errors::MalojaError;
 
@@ -22,7 +26,17 @@ fn get_database_path() -> PathBuf {
     FOLDERS.data.join("maloja.sqlite")
 }
 
-pub async fn init_db() -> Result<(), MalojaError> {
+fn backup_folder() -> PathBuf {
+    FOLDERS.data.join("backups")
+}
+
+const MAX_CONNECTIONS: u32 = 8;
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Every SQLite database file starts with this
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Opens the connection pool, checks the schema and returns the pool to be shared by the whole application
+pub async fn init_db() -> Result<DatabaseConnection, MalojaError> {
 
     let db = connect().await?;
     assert_eq!(db.get_database_backend(), DbBackend::Sqlite);
@@ -35,60 +49,119 @@ pub async fn init_db() -> Result<(), MalojaError> {
     log::info!("Using SQLite {}", version);
 
     log::info!("Checking Database schema...");
-    create_tables(&db).await;
-    log::info!("Checking imports...");
-    match import::import().await {
-        Ok((imported, failed)) => {
-            log::info!("Imported {} files, failed {}.", imported, failed);
-        }
-        Err(_) => {
-            log::error!("Failed to check for imports...");
-        }
-    };
-
-    Ok(())
+    migrations::migrate(&db).await?;
+    refresh_scrobble_span(&db).await?;
+    Ok(db)
 }
 
+/// Entities are looked up before they are created, so two submissions introducing the same new artist at once
+/// would both try to create it. Operations that create entities hold this lock (SQLite only has a single writer anyway)
+pub(crate) static WRITE_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// First and last scrobble, which are the boundaries of all time. Loaded again after writes
+static SCROBBLE_SPAN: RwLock<Option<(i64, i64)>> = RwLock::new(None);
+static SCROBBLE_SPAN_STALE: AtomicBool = AtomicBool::new(true);
+
 /// This function should be called every time the database has been written to and is in a new consistent state
 /// (so not after every single atomic write, but logical write operations)
 pub fn mark_db_write() {
-    // for the future
+    SCROBBLE_SPAN_STALE.store(true, Ordering::Release);
+}
+
+/// Same as `mark_db_write`, for writes that add or remove scrobbles. The first and last scrobble are loaded again
+/// right away, so that everything afterwards sees the new boundaries of all time, not just the next request
+pub async fn mark_scrobble_write(db: &DatabaseConnection) {
+    mark_db_write();
+    if let Err(e) = refresh_scrobble_span(db).await {
+        log::error!("Could not load the first and last scrobble: {:?}", e);
+    }
+}
+
+/// Timestamps of the first and last scrobble as of the last refresh, nothing if there are no scrobbles
+pub fn scrobble_span() -> Option<(i64, i64)> {
+    *SCROBBLE_SPAN.read().expect("Scrobble span is never poisoned")
 }
 
+/// Loads the first and last scrobble again, if there has been a write since the last time
+pub async fn refresh_scrobble_span(db: &DatabaseConnection) -> Result<(), MalojaError> {
+    // writes during the query mark it as stale again, so they're picked up next time
+    if !SCROBBLE_SPAN_STALE.swap(false, Ordering::AcqRel) {
+        return Ok(());
+    }
+    match repository::scrobble_span(db).await {
+        Ok(span) => {
+            *SCROBBLE_SPAN.write().expect("Scrobble span is never poisoned") = span;
+            Ok(())
+        }
+        Err(e) => {
+            SCROBBLE_SPAN_STALE.store(true, Ordering::Release);
+            Err(e)
+        }
+    }
+}
+
+/// Creates a new connection pool. This should only happen once, everything else uses the pool from `init_db`
 pub async fn connect() -> Result<DatabaseConnection, MalojaError> {
-    let dbfile = get_database_path().display().to_string();
-    let dbstring = format!("sqlite://{}?mode=rwc", dbfile);
-    let mut dboptions = ConnectOptions::new(dbstring);
-    dboptions.sqlx_logging(false);
-    match Database::connect(dboptions).await {
-        Ok(c) => Ok(c),
+    let options = SqliteConnectOptions::new()
+        .filename(get_database_path())
+        .create_if_missing(true)
+        // readers don't block the writer and vice versa, which matters as soon as pages and scrobbles come in together
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(SqliteSynchronous::Normal)
+        .busy_timeout(BUSY_TIMEOUT)
+        .foreign_keys(true)
+        .disable_statement_logging();
+    match SqlitePoolOptions::new().max_connections(MAX_CONNECTIONS).connect_with(options).await {
+        Ok(pool) => Ok(DatabaseConnection::from(pool)),
         Err(e) => {
             Err(MalojaError::DatabaseConnectionError { message: e.to_string() })
         }
     }
-    
 }
 
-pub async fn create_tables(db: &DbConn) {
-
-    // TODO: proper migrations
-
-    create_table(db, Scrobble).await;
-    create_table(db, Track).await;
-    create_table(db, Artist).await;
-    create_table(db, Album).await;
-
-    create_table(db, TrackArtist).await;
-    create_table(db, AlbumArtist).await;
+/// Writes a consistent copy of the database to the backup folder and returns its path
+pub async fn backup_database(db: &DbConn, label: &str) -> Result<PathBuf, MalojaError> {
+    let folder = backup_folder();
+    fs::create_dir_all(&folder)?;
+    let path = folder.join(format!("maloja_{}_{}.sqlite", chrono::Utc::now().format("%Y%m%d_%H%M%S"), label));
+    // unlike copying the file, this also works while other connections are writing
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        "VACUUM INTO ?",
+        [path.display().to_string().into()],
+    )).await?;
+    Ok(path)
 }
 
-async fn create_table<E: sea_orm::EntityTrait>(db: &DbConn, entity: E) {
-    let backend = db.get_database_backend();
-    let schema = Schema::new(backend);
-    let stmt = schema.create_table_from_entity(entity).if_not_exists().to_owned();
-    // TODO: wtf is even going on here
-    let statement = backend.build(&stmt);
-    let result = db.execute(statement).await.expect("wut");
+/// Replaces the database with a backup. This has to happen before the pool is opened.
+/// The current database is backed up first, the path of that backup is returned
+pub async fn restore_database(backup: &Path) -> Result<Option<PathBuf>, MalojaError> {
+    let mut header = [0u8; 16];
+    fs::File::open(backup)?.read_exact(&mut header)?;
+    if &header != SQLITE_HEADER {
+        return Err(MalojaError::ParseError { message: format!("{} is not a database backup", backup.display()) });
+    }
 
+    let path = get_database_path();
+    let previous = match path.exists() {
+        true => {
+            let db = connect().await?;
+            let previous = backup_database(&db, "pre_restore").await?;
+            db.close().await?;
+            Some(previous)
+        }
+        false => None,
+    };
+    // the write-ahead log belongs to the old database and would be applied to the restored one
+    for suffix in ["-wal", "-shm"] {
+        let mut file = path.clone().into_os_string();
+        file.push(suffix);
+        if let Err(e) = fs::remove_file(&file) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+    }
+    fs::copy(backup, &path)?;
+    Ok(previous)
 }
-
@@ -0,0 +1,99 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, NotSet, QueryFilter, QueryOrder};
+use sea_orm::ActiveValue::Set;
+use crate::database::mark_db_write;
+use crate::database::errors::MalojaError;
+use crate::entity::api_key::{Entity as ApiKey, ActiveModel as ApiKeyActiveModel, Column as ApiKeyColumn, ApiKeyRead, ApiKeyWrite};
+use crate::entity::session::{Entity as Session, ActiveModel as SessionActiveModel, Column as SessionColumn};
+
+const KEY_LENGTH: usize = 64;
+const SECRET_LENGTH: usize = 32;
+
+fn generate_random_string(length: usize) -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(length).map(char::from).collect()
+}
+
+pub fn generate_key() -> String {
+    generate_random_string(KEY_LENGTH)
+}
+
+pub fn generate_secret() -> String {
+    generate_random_string(SECRET_LENGTH)
+}
+
+pub async fn api_keys(db: &DatabaseConnection) -> Result<Vec<ApiKeyRead>, MalojaError> {
+    let result = ApiKey::find()
+        .order_by_asc(ApiKeyColumn::Id)
+        .all(db).await?;
+    Ok(result.into_iter().map(ApiKeyRead::from).collect())
+}
+
+pub async fn create_api_key(input: ApiKeyWrite, db: &DatabaseConnection) -> Result<ApiKeyRead, MalojaError> {
+    let key = input.key.unwrap_or_else(generate_key);
+    let secret = input.secret.unwrap_or_else(generate_secret);
+    if key.is_empty() || secret.is_empty() {
+        return Err(MalojaError::ParseError { message: "API key and secret must not be empty".to_string() });
+    }
+    if ApiKey::find().filter(ApiKeyColumn::Key.eq(&key)).one(db).await?.is_some() {
+        return Err(MalojaError::ParseError { message: "API key already exists".to_string() });
+    }
+    let result = ApiKey::insert(ApiKeyActiveModel {
+        id: NotSet,
+        key: Set(key),
+        secret: Set(secret),
+        name: Set(input.name),
+        description: Set(input.description),
+        created: Set(chrono::Utc::now().timestamp()),
+    }).exec_with_returning(db).await?;
+
+    mark_db_write();
+    Ok(result.into())
+}
+
+pub async fn delete_api_key(id: u32, db: &DatabaseConnection) -> Result<(), MalojaError> {
+    // sessions are only valid as long as the key that authenticated them
+    Session::delete_many()
+        .filter(SessionColumn::ApiKeyId.eq(id))
+        .exec(db).await?;
+    let result = ApiKey::delete_by_id(id).exec(db).await?;
+    if result.rows_affected == 0 {
+        return Err(MalojaError::ApiKeyNotFound { id });
+    }
+    mark_db_write();
+    Ok(())
+}
+
+/// Returns the matching key, or an error if the key is not known
+pub async fn validate_api_key(key: &str, db: &DatabaseConnection) -> Result<ApiKeyRead, MalojaError> {
+    match ApiKey::find().filter(ApiKeyColumn::Key.eq(key)).one(db).await? {
+        Some(model) => Ok(model.into()),
+        None => Err(MalojaError::AuthenticationError { message: "Invalid API key".to_string() }),
+    }
+}
+
+/// Creates a new session for protocols that exchange their credentials for a session key, and returns that key
+pub async fn create_session(api_key_id: u32, db: &DatabaseConnection) -> Result<String, MalojaError> {
+    let session_key = generate_secret();
+    Session::insert(SessionActiveModel {
+        id: NotSet,
+        session_key: Set(session_key.clone()),
+        api_key_id: Set(api_key_id),
+        created: Set(chrono::Utc::now().timestamp()),
+    }).exec(db).await?;
+
+    mark_db_write();
+    Ok(session_key)
+}
+
+/// Returns the key that authenticated the session, or an error if the session is not known
+pub async fn validate_session(session_key: &str, db: &DatabaseConnection) -> Result<ApiKeyRead, MalojaError> {
+    let result = Session::find()
+        .filter(SessionColumn::SessionKey.eq(session_key))
+        .find_also_related(ApiKey)
+        .one(db).await?;
+    match result {
+        Some((_, Some(api_key))) => Ok(api_key.into()),
+        _ => Err(MalojaError::AuthenticationError { message: "Invalid session key".to_string() }),
+    }
+}
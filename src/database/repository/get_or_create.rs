@@ -1,8 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use log::debug;
-use sea_orm::{ColumnTrait, DbErr, EntityTrait, NotSet, QueryFilter};
+use sea_orm::{ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, NotSet, QueryFilter, TransactionTrait};
 use sea_orm::ActiveValue::Set;
-use crate::database::{connect, mark_db_write};
+use crate::database::{mark_db_write, mark_scrobble_write, WRITE_LOCK};
 use crate::database::errors::MalojaError;
 use crate::entity::{
     album::{Entity as Album, Model as AlbumModel, ActiveModel as AlbumActiveModel, Column as AlbumColumn, AlbumWrite, AlbumRead},
@@ -16,16 +16,34 @@ use crate::entity::{
 /// How many entities should be inserted into the Database in one go
 const BATCH_SIZE: usize = 250;
 
-fn normalize(input: &str) -> String {
+pub(crate) fn normalize(input: &str) -> String {
     input.to_lowercase().replace("_", "-").replace(" ", "-")
 }
 
+/// Identity of a track without an ID: normalized title and the sorted IDs of all its artists
+/// (artists need to exist already)
+fn track_key(title: &str, track: &TrackWrite, artist_map: &HashMap<ArtistWrite, ArtistModel>) -> (String, Vec<u32>) {
+    let artists = [track.primary_artists.clone().unwrap_or_default(), track.secondary_artists.clone().unwrap_or_default()]
+        .concat();
+    let mut artist_ids = artists.into_iter().map(|x| artist_map[&x].id).collect::<Vec<u32>>();
+    artist_ids.sort();
+    artist_ids.dedup();
+    (normalize(title), artist_ids)
+}
+
+/// Identity of an album without an ID: normalized title and the sorted IDs of its album artists
+fn album_key(title: &str, album: &AlbumWrite, artist_map: &HashMap<ArtistWrite, ArtistModel>) -> (String, Vec<u32>) {
+    let mut artist_ids = album.album_artists.clone().unwrap_or_default().into_iter().map(|x| artist_map[&x].id).collect::<Vec<u32>>();
+    artist_ids.sort();
+    artist_ids.dedup();
+    (normalize(title), artist_ids)
+}
+
 // alrighty this time we're doing it organized from the start unlike the python monstrosity
 // this is totally gonna work this time lmao
 // link the relevant xkcd here
 #[allow(clippy::collapsible_else_if)]
-pub async fn get_or_create_artists(input: Vec<ArtistWrite>) -> Result<HashMap<ArtistWrite, ArtistModel>, MalojaError> {
-    let db = connect().await?;
+pub async fn get_or_create_artists(input: Vec<ArtistWrite>, db: &impl ConnectionTrait) -> Result<HashMap<ArtistWrite, ArtistModel>, MalojaError> {
     let mut result: HashMap<ArtistWrite, Option<ArtistModel>> = HashMap::new();
     input.clone().into_iter().for_each(|artist| {
         result.insert(artist, None);
@@ -40,25 +58,30 @@ pub async fn get_or_create_artists(input: Vec<ArtistWrite>) -> Result<HashMap<Ar
     // supplying an ID indicated the client wants to refer to an existing entity - mismatching other info is ignored
 
     let mut id_map: HashMap<u32, Vec<&ArtistWrite>> = HashMap::new();
+    let mut mbid_map: HashMap<String, Vec<&ArtistWrite>> = HashMap::new();
     let mut name_map: HashMap<String, Vec<&ArtistWrite>> = HashMap::new();
     for (index, inp) in input.iter().enumerate() {
         if let Some(id) = &inp.id {
-            id_map.entry(*id).or_insert(vec![]).push(inp);
+            id_map.entry(*id).or_default().push(inp);
         }
         // if we have an ID supplied, we're not gonna use anything else, even if the ID doesn't work - so all other maps in else
         else {
+            if let Some(mbid) = &inp.mbid {
+                mbid_map.entry(mbid.clone()).or_default().push(inp);
+            }
             if let Some(name) = &inp.name {
-                name_map.entry(normalize(name)).or_insert(vec![]).push(inp);
+                name_map.entry(normalize(name)).or_default().push(inp);
             }
         }
     }
     let id_list: Vec<u32> = id_map.keys().cloned().collect();
+    let mbid_list: Vec<String> = mbid_map.keys().cloned().collect();
     let name_list: Vec<String> = name_map.keys().cloned().collect();
 
     // IDs
     let db_result = Artist::find()
         .filter(ArtistColumn::Id.is_in(id_list))
-        .all(&db).await.unwrap();
+        .all(db).await?;
     for model in db_result {
         let writes = &id_map[&model.id];
         for write in writes {
@@ -67,18 +90,32 @@ pub async fn get_or_create_artists(input: Vec<ArtistWrite>) -> Result<HashMap<Ar
         }
 
     }
-    // TODO: make sure no supplied IDs are unused - this should be an error instead of just checking for other match methods
+    // supplied IDs refer to existing entities, so one that doesn't exist is an error rather than a reason to create one
+    if let Some((&id, _)) = id_map.iter().find(|(_, writes)| writes.iter().any(|w| result[*w].is_none())) {
+        return Err(MalojaError::ArtistNotFound { id });
+    }
 
-    // TODO: mbid and spotify_id
+    // MBIDs - these are more reliable than names, so they're checked first and names only fill the gaps
+    let db_result = Artist::find()
+        .filter(ArtistColumn::Mbid.is_in(mbid_list))
+        .all(db).await?;
+    for model in db_result {
+        let writes = &mbid_map[model.mbid.as_ref().expect("Matched on mbid")];
+        for write in writes {
+            result.insert(write.to_owned().clone(), Some(model.clone()));
+        }
+    }
+
+    // TODO: spotify_id
 
     // Names
     let db_result = Artist::find()
         .filter(ArtistColumn::NameNormalized.is_in(name_list))
-        .all(&db).await.unwrap();
+        .all(db).await?;
     for model in db_result {
         let writes = &name_map[&model.name_normalized];
         for write in writes {
-            result.insert(write.to_owned().clone(), Some(model.clone()));
+            result.entry(write.to_owned().clone()).or_default().get_or_insert(model.clone());
         }
     }
 
@@ -90,35 +127,46 @@ pub async fn get_or_create_artists(input: Vec<ArtistWrite>) -> Result<HashMap<Ar
             notfound.push(write);
         }
     }
-    // TODO: deduplicate!
     if !notfound.is_empty() {
-        let inserts: Vec<ArtistActiveModel> = notfound.iter().map(|&x| {
-            assert!(x.name.is_some()); //TODO
+        // several writes can describe the same new artist (e.g. once with and once without mbid)
+        // so we only insert one row per normalized name, and never the same mbid twice
+        let mut inserts: Vec<ArtistActiveModel> = vec![];
+        let mut inserted_names: HashSet<String> = HashSet::new();
+        let mut inserted_mbids: HashSet<String> = HashSet::new();
+        for &x in notfound.iter() {
+            if x.name.is_none() {
+                return Err(MalojaError::ParseError { message: "New artist needs a name".to_string() });
+            }
             let x = x.to_owned();
-            ArtistActiveModel {
+            let name_normalized = normalize(&x.name.clone().unwrap());
+            if !inserted_names.insert(name_normalized.clone()) {
+                continue;
+            }
+            let mbid = x.mbid.filter(|mbid| inserted_mbids.insert(mbid.clone()));
+            inserts.push(ArtistActiveModel {
                 id: NotSet,
                 name: Set(x.name.clone().unwrap()),
-                name_normalized: Set(normalize(&x.name.clone().unwrap())),
-                mbid: Set(x.mbid),
+                name_normalized: Set(name_normalized),
+                mbid: Set(mbid),
                 spotify_id: Set(x.spotify_id),
-            }
-        }).collect();
+            });
+        }
 
         //let inserts = inserts.chunks(1).next().unwrap().to_vec();
         let amount_inserts = &inserts.len();
         // this doesnt seem to return all models
         // https://github.com/SeaQL/sea-orm/discussions/2191
         // so for now we just insert and call the whole thing again i guess?
-        //let db_result: Vec<ArtistModel> = Artist::insert_many(inserts).exec_with_returning(&db).await.unwrap();
+        //let db_result: Vec<ArtistModel> = Artist::insert_many(inserts).exec_with_returning(db).await.unwrap();
         for chunk in inserts.chunks(BATCH_SIZE) {
             let chunk_inserts = chunk.to_vec();
-            let db_result = Artist::insert_many(chunk_inserts).exec(&db).await.unwrap();
+            let db_result = Artist::insert_many(chunk_inserts).exec(db).await?;
         }
 
         mark_db_write();
 
         debug!("Inserted {:?} Artists", amount_inserts);
-        Box::pin(get_or_create_artists(input)).await
+        Box::pin(get_or_create_artists(input, db)).await
     }
     else {
         let result: HashMap<ArtistWrite, ArtistModel> = result.into_iter().map(|(k,v)| (k, v.expect("This should not happen!").clone())).collect();
@@ -128,8 +176,7 @@ pub async fn get_or_create_artists(input: Vec<ArtistWrite>) -> Result<HashMap<Ar
 }
 
 #[allow(clippy::collapsible_else_if)]
-pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<TrackWrite, TrackModel>, MalojaError> {
-    let db = connect().await?;
+pub async fn get_or_create_tracks(input: Vec<TrackWrite>, db: &impl ConnectionTrait) -> Result<HashMap<TrackWrite, TrackModel>, MalojaError> {
     let mut result: HashMap<TrackWrite, Option<TrackModel>> = HashMap::new();
     input.clone().into_iter().for_each(|track| {
         result.insert(track, None);
@@ -137,39 +184,44 @@ pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<Trac
 
     // make sure all artists exist
     let artists = input.iter().map(|t| [t.primary_artists.clone().unwrap_or_default(), t.secondary_artists.clone().unwrap_or_default()].concat()).flatten().collect();
-    let artist_map = get_or_create_artists(artists).await?;
+    let artist_map = get_or_create_artists(artists, db).await?;
 
     // make sure all albums exist
     let albums: Vec<AlbumWrite> = input.iter().filter_map(|t| t.album.clone()).collect();
-    let album_map = get_or_create_albums(albums).await?;
+    let album_map = get_or_create_albums(albums, db).await?;
 
 
     // as above, but now the name alone isnt enough - we need name and artist exact set match (primary secondary doesnt matter)
     let mut id_map: HashMap<u32, Vec<&TrackWrite>> = HashMap::new();
+    let mut mbid_map: HashMap<String, Vec<&TrackWrite>> = HashMap::new();
+    let mut spotify_map: HashMap<String, Vec<&TrackWrite>> = HashMap::new();
     let mut title_artists_map: HashMap<(String, Vec<u32>), Vec<&TrackWrite>> = HashMap::new();
     for (index, inp) in input.iter().enumerate() {
         if let Some(id) = &inp.id {
-            id_map.entry(*id).or_insert(vec![]).push(inp);
+            id_map.entry(*id).or_default().push(inp);
         }
         // if we have an ID supplied, we're not gonna use anything else, even if the ID doesn't work - so all other maps in else
         else {
+            if let Some(mbid) = &inp.mbid {
+                mbid_map.entry(mbid.clone()).or_default().push(inp);
+            }
+            if let Some(spotify_id) = &inp.spotify_id {
+                spotify_map.entry(spotify_id.clone()).or_default().push(inp);
+            }
             if let Some(title) = &inp.title {
-                // artists should now all exist so we can get IDs
-                let artists = [inp.to_owned().primary_artists.unwrap_or_default(), inp.to_owned().secondary_artists.unwrap_or_default()]
-                    .concat();
-                let mut artist_ids = artists.into_iter().map(|x| artist_map[&x].id).collect::<Vec<u32>>();
-                artist_ids.sort();
-                title_artists_map.entry((normalize(title),artist_ids)).or_insert(vec![]).push(inp);
+                title_artists_map.entry(track_key(title, inp, &artist_map)).or_default().push(inp);
             }
         }
     }
     let id_list: Vec<u32> = id_map.keys().cloned().collect();
+    let mbid_list: Vec<String> = mbid_map.keys().cloned().collect();
+    let spotify_list: Vec<String> = spotify_map.keys().cloned().collect();
     let title_artists_list: Vec<(String, Vec<u32>)> = title_artists_map.keys().cloned().collect();
 
     // IDs
     let db_result = Track::find()
         .filter(TrackColumn::Id.is_in(id_list))
-        .all(&db).await.unwrap();
+        .all(db).await?;
     for model in db_result {
         let writes = &id_map[&model.id];
         for write in writes {
@@ -177,9 +229,33 @@ pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<Trac
         }
 
     }
-    // TODO: make sure no supplied IDs are unused - this should be an error instead of just checking for other match methods
+    // supplied IDs refer to existing entities, so one that doesn't exist is an error rather than a reason to create one
+    if let Some((&id, _)) = id_map.iter().find(|(_, writes)| writes.iter().any(|w| result[*w].is_none())) {
+        return Err(MalojaError::TrackNotFound { id });
+    }
+
+    // MBIDs
+    let db_result = Track::find()
+        .filter(TrackColumn::Mbid.is_in(mbid_list))
+        .all(db).await?;
+    for model in db_result {
+        let writes = &mbid_map[model.mbid.as_ref().expect("Matched on mbid")];
+        for write in writes {
+            result.insert(write.to_owned().clone(), Some(model.clone()));
+        }
+    }
 
-    // TODO: mbid and spotify_id
+    // Spotify IDs
+    // imports from spotify have the same ID under different titles over the years. an MBID match still comes first
+    let db_result = Track::find()
+        .filter(TrackColumn::SpotifyId.is_in(spotify_list))
+        .all(db).await?;
+    for model in db_result {
+        let writes = &spotify_map[model.spotify_id.as_ref().expect("Matched on spotify_id")];
+        for write in writes {
+            result.entry(write.to_owned().clone()).or_default().get_or_insert(model.clone());
+        }
+    }
 
     // Titles + Artists
     // we'll just ask the database for the matching titles to avoid some crazy super query.
@@ -190,7 +266,7 @@ pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<Trac
         //.join(JoinType::LeftJoin, TrackRelation::TrackArtist.def())
         //.select_also(TrackArtistEntity)
         .find_with_related(Artist)
-        .all(&db).await.unwrap();
+        .all(db).await?;
     for (track_model, artist_models) in db_result {
         let mut artist_ids: Vec<u32> = artist_models.iter().map(|x| x.id).collect();
         artist_ids.sort();
@@ -198,7 +274,7 @@ pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<Trac
         if title_artists_map.contains_key(&potential_key) {
             let writes = &title_artists_map[&potential_key];
             for write in writes {
-                result.insert(write.to_owned().clone(), Some(track_model.clone()));
+                result.entry(write.to_owned().clone()).or_default().get_or_insert(track_model.clone());
             }
         }
     }
@@ -214,61 +290,79 @@ pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<Trac
         }
     }
     if !notfound.is_empty() {
-        let inserts: Vec<(TrackActiveModel, Vec<ArtistWrite>, Vec<ArtistWrite>)> = notfound.iter().map(|&x| {
-            assert!(x.title.is_some());
+        // same as with artists, only one new track per title and artist set
+        let mut inserts: Vec<(TrackActiveModel, Vec<ArtistWrite>, Vec<ArtistWrite>)> = vec![];
+        let mut inserted_keys: HashSet<(String, Vec<u32>)> = HashSet::new();
+        let mut inserted_mbids: HashSet<String> = HashSet::new();
+        let mut inserted_spotify_ids: HashSet<String> = HashSet::new();
+        for &x in notfound.iter() {
+            if x.title.is_none() {
+                return Err(MalojaError::ParseError { message: "New track needs a title".to_string() });
+            }
+            // the track that gets this spotify ID is the one this write will be found as in the next round
+            if x.spotify_id.as_ref().is_some_and(|spotify_id| inserted_spotify_ids.contains(spotify_id)) {
+                continue;
+            }
             // TODO: do we enforce artists?
+            if !inserted_keys.insert(track_key(x.title.as_ref().unwrap(), x, &artist_map)) {
+                continue;
+            }
             let x = x.to_owned();
+            let mbid = x.mbid.filter(|mbid| inserted_mbids.insert(mbid.clone()));
+            let spotify_id = x.spotify_id.inspect(|spotify_id| { inserted_spotify_ids.insert(spotify_id.clone()); });
 
-            (TrackActiveModel {
+            inserts.push((TrackActiveModel {
                 id: NotSet,
                 title: Set(x.title.clone().unwrap()),
                 title_normalized: Set(normalize(&x.title.clone().unwrap())),
                 track_length: Set(x.track_length),
                 album_id: if let Some(album) = x.album { Set(Some(album_map.get(&album).unwrap().id)) } else { NotSet },
-                mbid: Set(x.mbid.clone()),
-                spotify_id: Set(x.spotify_id.clone()),
+                mbid: Set(mbid),
+                spotify_id: Set(spotify_id),
             },
              x.primary_artists.unwrap_or_default(),
-             x.secondary_artists.unwrap_or_default())
-        }).collect();
+             x.secondary_artists.unwrap_or_default()));
+        }
 
         let amount_inserts = &inserts.len();
 
         // for now, insert each one individually so we can actually get the ID
         // i really hope this isnt the permanent solution
         for (insert, primary_artists, secondary_artists) in inserts {
-            let db_result = Track::insert(insert).exec_with_returning(&db).await.unwrap();
+            let db_result = Track::insert(insert).exec_with_returning(db).await?;
 
 
             // TODO: MAKE THIS NOT SHIT
             let track_id = db_result.id;
 
-            let track_artist_inserts_primary: Vec<TrackArtistActiveModel> = primary_artists.iter().map(|x| {
+            // different writes can resolve to the same artist, each one can only be linked once
+            let mut linked_artists: HashSet<u32> = HashSet::new();
+            let track_artist_inserts_primary: Vec<TrackArtistActiveModel> = primary_artists.iter().filter_map(|x| {
                 // get the mapped model that definitely has an ID now
                 let artist_model = &artist_map[x];
-                TrackArtistActiveModel {
+                linked_artists.insert(artist_model.id).then(|| TrackArtistActiveModel {
                     track_id: Set(track_id),
                     artist_id: Set(artist_model.id),
                     primary: Set(true),
                     artist_alias: Default::default(),
-                }
+                })
             }).collect();
-            let track_artist_inserts_secondary: Vec<TrackArtistActiveModel> = secondary_artists.iter().map(|x| {
+            let track_artist_inserts_secondary: Vec<TrackArtistActiveModel> = secondary_artists.iter().filter_map(|x| {
                 let artist_model = &artist_map[x];
-                TrackArtistActiveModel {
+                linked_artists.insert(artist_model.id).then(|| TrackArtistActiveModel {
                     track_id: Set(track_id),
                     artist_id: Set(artist_model.id),
                     primary: Set(false),
                     artist_alias: Default::default(),
-                }
+                })
             }).collect();
 
 
             if !track_artist_inserts_primary.is_empty() {
-                let db_result = TrackArtist::insert_many(track_artist_inserts_primary).exec(&db).await.unwrap();
+                TrackArtist::insert_many(track_artist_inserts_primary).exec(db).await?;
             }
             if !track_artist_inserts_secondary.is_empty() {
-                let db_result = TrackArtist::insert_many(track_artist_inserts_secondary).exec(&db).await.unwrap();
+                TrackArtist::insert_many(track_artist_inserts_secondary).exec(db).await?;
             }
 
 
@@ -277,7 +371,7 @@ pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<Trac
         mark_db_write();
 
         debug!("Inserted {:?} Tracks", amount_inserts);
-        Box::pin(get_or_create_tracks(input)).await
+        Box::pin(get_or_create_tracks(input, db)).await
     }
     else {
         let result: HashMap<TrackWrite, TrackModel> = result.into_iter().map(|(k,v)| (k, v.expect("This should not happen!").clone())).collect();
@@ -289,8 +383,7 @@ pub async fn get_or_create_tracks(input: Vec<TrackWrite>) -> Result<HashMap<Trac
 
 
 #[allow(clippy::collapsible_else_if)]
-pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<AlbumWrite, AlbumModel>, MalojaError> {
-    let db = connect().await?;
+pub async fn get_or_create_albums(input: Vec<AlbumWrite>, db: &impl ConnectionTrait) -> Result<HashMap<AlbumWrite, AlbumModel>, MalojaError> {
     let mut result: HashMap<AlbumWrite, Option<AlbumModel>> = HashMap::new();
     input.clone().into_iter().for_each(|album| {
         result.insert(album, None);
@@ -298,34 +391,35 @@ pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<Albu
 
     // make sure all artists exist
     let artists = input.iter().map(|a| a.album_artists.clone().unwrap_or_default()).flatten().collect();
-    let artist_map = get_or_create_artists(artists).await?;
+    let artist_map = get_or_create_artists(artists, db).await?;
 
 
     // as above, but now the name alone isnt enough - we need name and artist exact set match (primary secondary doesnt matter)
     let mut id_map: HashMap<u32, Vec<&AlbumWrite>> = HashMap::new();
+    let mut mbid_map: HashMap<String, Vec<&AlbumWrite>> = HashMap::new();
     let mut albumtitle_artists_map: HashMap<(String, Vec<u32>), Vec<&AlbumWrite>> = HashMap::new();
     for (index, inp) in input.iter().enumerate() {
         if let Some(id) = &inp.id {
-            id_map.entry(*id).or_insert(vec![]).push(inp);
+            id_map.entry(*id).or_default().push(inp);
         }
         // if we have an ID supplied, we're not gonna use anything else, even if the ID doesn't work - so all other maps in else
         else {
+            if let Some(mbid) = &inp.mbid {
+                mbid_map.entry(mbid.clone()).or_default().push(inp);
+            }
             if let Some(title) = &inp.album_title {
-                // artists should now all exist so we can get IDs
-                let artists = inp.to_owned().album_artists.unwrap_or_default();
-                let mut artist_ids = artists.into_iter().map(|x| artist_map[&x].id).collect::<Vec<u32>>();
-                artist_ids.sort();
-                albumtitle_artists_map.entry((normalize(title),artist_ids)).or_insert(vec![]).push(inp);
+                albumtitle_artists_map.entry(album_key(title, inp, &artist_map)).or_default().push(inp);
             }
         }
     }
     let id_list: Vec<u32> = id_map.keys().cloned().collect();
+    let mbid_list: Vec<String> = mbid_map.keys().cloned().collect();
     let albumtitle_artists_list: Vec<(String, Vec<u32>)> = albumtitle_artists_map.keys().cloned().collect();
 
     // IDs
     let db_result = Album::find()
         .filter(AlbumColumn::Id.is_in(id_list))
-        .all(&db).await.unwrap();
+        .all(db).await?;
     for model in db_result {
         let writes = &id_map[&model.id];
         for write in writes {
@@ -333,9 +427,23 @@ pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<Albu
         }
 
     }
-    // TODO: make sure no supplied IDs are unused - this should be an error instead of just checking for other match methods
+    // supplied IDs refer to existing entities, so one that doesn't exist is an error rather than a reason to create one
+    if let Some((&id, _)) = id_map.iter().find(|(_, writes)| writes.iter().any(|w| result[*w].is_none())) {
+        return Err(MalojaError::AlbumNotFound { id });
+    }
 
-    // TODO: mbid and spotify_id
+    // MBIDs
+    let db_result = Album::find()
+        .filter(AlbumColumn::Mbid.is_in(mbid_list))
+        .all(db).await?;
+    for model in db_result {
+        let writes = &mbid_map[model.mbid.as_ref().expect("Matched on mbid")];
+        for write in writes {
+            result.insert(write.to_owned().clone(), Some(model.clone()));
+        }
+    }
+
+    // TODO: spotify_id
 
     // Album Titles + Album Artists
     // we'll just ask the database for the matching titles to avoid some crazy super query.
@@ -346,7 +454,7 @@ pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<Albu
         //.join(JoinType::LeftJoin, TrackRelation::TrackArtist.def())
         //.select_also(TrackArtistEntity)
         .find_with_related(Artist)
-        .all(&db).await.unwrap();
+        .all(db).await?;
     for (album_model, artist_models) in db_result {
         let mut artist_ids: Vec<u32> = artist_models.iter().map(|x| x.id).collect();
         artist_ids.sort();
@@ -354,7 +462,7 @@ pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<Albu
         if albumtitle_artists_map.contains_key(&potential_key) {
             let writes = &albumtitle_artists_map[&potential_key];
             for write in writes {
-                result.insert(write.to_owned().clone(), Some(album_model.clone()));
+                result.entry(write.to_owned().clone()).or_default().get_or_insert(album_model.clone());
             }
         }
     }
@@ -368,44 +476,54 @@ pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<Albu
         }
     }
     if !notfound.is_empty() {
-        let inserts: Vec<(AlbumActiveModel, Vec<ArtistWrite>)> = notfound.iter().map(|&x| {
-            assert!(x.album_title.is_some());
+        let mut inserts: Vec<(AlbumActiveModel, Vec<ArtistWrite>)> = vec![];
+        let mut inserted_keys: HashSet<(String, Vec<u32>)> = HashSet::new();
+        let mut inserted_mbids: HashSet<String> = HashSet::new();
+        for &x in notfound.iter() {
+            if x.album_title.is_none() {
+                return Err(MalojaError::ParseError { message: "New album needs a title".to_string() });
+            }
             // TODO: do we enforce artists?
+            if !inserted_keys.insert(album_key(x.album_title.as_ref().unwrap(), x, &artist_map)) {
+                continue;
+            }
             let x = x.to_owned();
+            let mbid = x.mbid.filter(|mbid| inserted_mbids.insert(mbid.clone()));
 
-            (AlbumActiveModel {
+            inserts.push((AlbumActiveModel {
                 id: NotSet,
                 album_title: Set(x.album_title.clone().unwrap()),
                 album_title_normalized: Set(normalize(&x.album_title.clone().unwrap())),
-                mbid: Set(x.mbid),
+                mbid: Set(mbid),
                 spotify_id: Set(x.spotify_id),
             },
-             x.album_artists.unwrap_or_default())
-        }).collect();
+             x.album_artists.unwrap_or_default()));
+        }
 
         let amount_inserts = &inserts.len();
 
         // for now, insert each one individually so we can actually get the ID
         // i really hope this isnt the permanent solution
         for (insert, artists) in inserts {
-            let db_result = Album::insert(insert).exec_with_returning(&db).await.unwrap();
+            let db_result = Album::insert(insert).exec_with_returning(db).await?;
 
 
             // TODO: MAKE THIS NOT SHIT
             let album_id = db_result.id;
 
-            let album_artist_inserts: Vec<AlbumArtistActiveModel> = artists.iter().map(|x| {
+            let mut linked_artists: HashSet<u32> = HashSet::new();
+            let album_artist_inserts: Vec<AlbumArtistActiveModel> = artists.iter().filter_map(|x| {
                 // get the mapped model that definitely has an ID now
                 let artist_model = &artist_map[x];
-                AlbumArtistActiveModel {
+                linked_artists.insert(artist_model.id).then_some(AlbumArtistActiveModel {
                     album_id: Set(album_id),
                     artist_id: Set(artist_model.id),
-                }
+                })
             }).collect();
 
 
             if !album_artist_inserts.is_empty() {
-                let db_result = AlbumArtist::insert_many(album_artist_inserts).exec(&db).await.unwrap();
+                let db_result = AlbumArtist::insert_many(album_artist_inserts).exec(db).await?;
             }
 
 
@@ -414,7 +532,7 @@ pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<Albu
         mark_db_write();
 
         debug!("Inserted {:?} Albums", amount_inserts);
-        Box::pin(get_or_create_albums(input)).await
+        Box::pin(get_or_create_albums(input, db)).await
     }
     else {
         let result: HashMap<AlbumWrite, AlbumModel> = result.into_iter().map(|(k,v)| (k, v.expect("This should not happen!").clone())).collect();
@@ -424,60 +542,92 @@ pub async fn get_or_create_albums(input: Vec<AlbumWrite>) -> Result<HashMap<Albu
 }
 
 
+pub async fn create_scrobbles(input: Vec<ScrobbleWrite>, fail_on_existing: bool, db: &DatabaseConnection) -> Result<HashMap<ScrobbleWrite, ScrobbleModel>, MalojaError> {
+    create_scrobbles_counted(input, fail_on_existing, db).await.map(|(result, _)| result)
+}
+
+/// Same as `create_scrobbles`, but also returns how many of the scrobbles were actually new
+pub async fn create_scrobbles_counted(input: Vec<ScrobbleWrite>, fail_on_existing: bool, db: &DatabaseConnection) -> Result<(HashMap<ScrobbleWrite, ScrobbleModel>, usize), MalojaError> {
+    let _lock = WRITE_LOCK.lock().await;
+    // new artists, albums and tracks are only kept together with their scrobbles, a rejected submission leaves nothing behind
+    let transaction = db.begin().await?;
+    let mut inserted = 0;
+    let result = create_scrobbles_locked(input, fail_on_existing, &mut inserted, &transaction).await;
+    match result {
+        Ok(result) => {
+            transaction.commit().await?;
+            // the writes above are only visible now
+            mark_scrobble_write(db).await;
+            Ok((result, inserted))
+        }
+        Err(e) => {
+            transaction.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
 #[allow(clippy::collapsible_else_if)]
-pub async fn create_scrobbles(input: Vec<ScrobbleWrite>, fail_on_existing: bool) -> Result<HashMap<ScrobbleWrite, ScrobbleModel>, MalojaError> {
+async fn create_scrobbles_locked(input: Vec<ScrobbleWrite>, fail_on_existing: bool, inserted: &mut usize, db: &impl ConnectionTrait) -> Result<HashMap<ScrobbleWrite, ScrobbleModel>, MalojaError> {
     // this one is a bit different that the other entity ones because we never supply a scrobblewrite
     // as part of another entity to either create or fetch - scrobbles are only ever created (or patched?)
-    let db = connect().await?;
     let mut result: HashMap<ScrobbleWrite, Option<ScrobbleModel>> = HashMap::new();
     input.clone().into_iter().for_each(|scrobble| {
         result.insert(scrobble, None);
     });
 
-    // make sure all tracks exist
+    // a scrobble is identified by its timestamp and track. the same track twice in the same second is the same
+    // listen submitted twice, but different tracks in the same second are separate listens (e.g. from imports of
+    // offline caches), so we need to know the tracks before we can tell which scrobbles exist
     let tracks = input.iter().map(|s| s.track.clone()).collect();
-    let track_map = get_or_create_tracks(tracks).await?;
-
-
-    // here we have no matching. existing timestamp means existing scrobble, otherwise new
-    // normally supplying the ID is a clear indication someone is referring to an existing entity
-    // for scrobble, it is feasible to want to submit a new scrobble but use a timestamp that exists
-    // so TODO: distinguish between use cases? when do we even refer to existing scrobbles with a write?
+    let track_map = get_or_create_tracks(tracks, db).await?;
+    let identity = |write: &ScrobbleWrite| (write.timestamp, track_map[&write.track].id);
 
-    let mut ts_map: HashMap<i64, Vec<&ScrobbleWrite>> = HashMap::new();
-    for (index, inp) in input.iter().enumerate() {
-        ts_map.entry(inp.timestamp).or_insert(vec![]).push(inp);
+    let mut identity_map: HashMap<(i64, u32), Vec<&ScrobbleWrite>> = HashMap::new();
+    for inp in input.iter() {
+        identity_map.entry(identity(inp)).or_default().push(inp);
+    }
+    let ts_list: Vec<i64> = identity_map.keys().map(|(timestamp, _)| *timestamp).collect::<HashSet<i64>>().into_iter().collect();
+
+    let mut db_result: Vec<ScrobbleModel> = vec![];
+    for chunk in ts_list.chunks(BATCH_SIZE) {
+        let chunk_result = Scrobble::find()
+            .filter(ScrobbleColumn::Timestamp.is_in(chunk.to_vec()))
+            .all(db).await?;
+        db_result.extend(chunk_result.into_iter().filter(|model| identity_map.contains_key(&(model.timestamp, model.track_id))));
+    }
+    // check before creating any scrobbles, so a rejected submission doesn't leave half of them behind
+    if fail_on_existing {
+        if let Some(model) = db_result.first() {
+            return Err(MalojaError::ScrobbleExists { timestamp: model.timestamp });
+        }
+        if let Some((&(timestamp, _), _)) = identity_map.iter().find(|(_, writes)| writes.len() > 1) {
+            return Err(MalojaError::ScrobbleExists { timestamp });
+        }
     }
-    let ts_list: Vec<i64> = ts_map.keys().cloned().collect();
-
-    let db_result = Scrobble::find()
-        .filter(ScrobbleColumn::Timestamp.is_in(ts_list))
-        .all(&db).await.unwrap();
     for model in db_result {
-        let writes = &ts_map[&model.timestamp];
+        let writes = &identity_map[&(model.timestamp, model.track_id)];
         for write in writes {
             result.insert(write.to_owned().clone(), Some(model.clone()));
         }
 
     }
 
-    // TODO error on found
-
 
-    let mut notfound: Vec<&ScrobbleWrite> = vec![];
-    for (write, opt) in result.iter() {
-        if opt.is_none() {
-            notfound.push(write);
-        }
-    }
+    // in submission order, so that the first of several identical new scrobbles wins
+    let mut inserted_identities: HashSet<(i64, u32)> = HashSet::new();
+    let notfound: Vec<&ScrobbleWrite> = input.iter()
+        .filter(|write| result[*write].is_none() && inserted_identities.insert(identity(write)))
+        .collect();
     if !notfound.is_empty() {
         let inserts: Vec<ScrobbleActiveModel> = notfound.iter().map(|&x| {
             let x = x.to_owned();
 
             ScrobbleActiveModel {
+                id: NotSet,
                 timestamp: Set(x.timestamp),
                 track_id: Set(track_map[&x.track].id),
-                raw_scrobble: Set("{}".into()),
+                raw_scrobble: Set(serde_json::to_value(&x.raw_scrobble).expect("Raw scrobble is valid json")),
                 origin: Set(x.origin),
                 listen_duration: Set(x.listen_duration),
             }
@@ -489,13 +639,14 @@ pub async fn create_scrobbles(input: Vec<ScrobbleWrite>, fail_on_existing: bool)
         // i really hope this isnt the permanent solution
         for chunk in inserts.chunks(BATCH_SIZE) {
             let chunk_inserts = chunk.to_vec();
-            let db_result = Scrobble::insert_many(chunk_inserts).exec(&db).await.unwrap();
+            Scrobble::insert_many(chunk_inserts).exec(db).await?;
         }
 
         mark_db_write();
 
         debug!("Inserted {:?} Scrobbles", amount_inserts);
-        Box::pin(create_scrobbles(input, false)).await
+        *inserted += amount_inserts;
+        Box::pin(create_scrobbles_locked(input, false, inserted, db)).await
     }
     else {
         let result: HashMap<ScrobbleWrite, ScrobbleModel> = result.into_iter().map(|(k,v)| (k, v.expect("This should not happen!").clone())).collect();
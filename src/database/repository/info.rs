@@ -1,34 +1,73 @@
-use sea_orm::DbErr;
-use crate::database::connect;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
 use crate::database::errors::MalojaError;
+use crate::database::repository::get_or_create::normalize;
 use crate::database::repository::{resolve_album_ids, resolve_artist_ids, resolve_track_ids};
 use crate::entity::album::AlbumRead;
-use crate::entity::artist::ArtistRead;
-use crate::entity::track::TrackRead;
+use crate::entity::artist::{ArtistRead, Entity as Artist, Column as ArtistColumn};
+use crate::entity::track::{TrackRead, Entity as Track, Column as TrackColumn};
 
-pub async fn artist_info(artist_id: u32) -> Result<ArtistRead, MalojaError> {
-    let db = connect().await?;
-    let result = resolve_artist_ids(vec![artist_id], &db).await;
+pub async fn artist_info(artist_id: u32, db: &DatabaseConnection) -> Result<ArtistRead, MalojaError> {
+    let result = resolve_artist_ids(vec![artist_id], db).await;
     match result.into_iter().next() {
         Some(result) => { Ok(result.1) }
         None => { Err(MalojaError::ArtistNotFound { id: artist_id }) }
     }
 }
 
-pub async fn track_info(track_id: u32) -> Result<TrackRead, MalojaError> {
-    let db = connect().await?;
-    let result = resolve_track_ids(vec![track_id], &db).await;
+pub async fn track_info(track_id: u32, db: &DatabaseConnection) -> Result<TrackRead, MalojaError> {
+    let result = resolve_track_ids(vec![track_id], db).await;
     match result.into_iter().next() {
         Some(result) => { Ok(result.1) }
         None => { Err(MalojaError::TrackNotFound { id: track_id }) }
     }
 }
 
-pub async fn album_info(album_id: u32) -> Result<AlbumRead, MalojaError> {
-    let db = connect().await?;
-    let result = resolve_album_ids(vec![album_id], &db).await;
+pub async fn album_info(album_id: u32, db: &DatabaseConnection) -> Result<AlbumRead, MalojaError> {
+    let result = resolve_album_ids(vec![album_id], db).await;
     match result.into_iter().next() {
         Some(result) => { Ok(result.1) }
         None => { Err(MalojaError::AlbumNotFound { id: album_id }) }
     }
-}
\ No newline at end of file
+}
+
+/// Finds an artist by name instead of ID, as the v1 API refers to them
+pub async fn artist_by_name(name: &str, db: &DatabaseConnection) -> Result<ArtistRead, MalojaError> {
+    let result = Artist::find()
+        .filter(ArtistColumn::NameNormalized.eq(normalize(name)))
+        .one(db).await?;
+    match result {
+        Some(model) => Ok(ArtistRead { id: model.id, name: model.name }),
+        None => Err(MalojaError::ArtistNameNotFound { name: name.to_string() }),
+    }
+}
+
+/// Finds a track by title and artist names. A track with exactly these artists is preferred,
+/// otherwise any track that credits all of them is accepted
+pub async fn track_by_title(artists: &[String], title: &str, db: &DatabaseConnection) -> Result<TrackRead, MalojaError> {
+    let ids = Track::find()
+        .filter(TrackColumn::TitleNormalized.eq(normalize(title)))
+        .order_by_asc(TrackColumn::Id)
+        .all(db).await?
+        .into_iter().map(|model| model.id).collect::<Vec<u32>>();
+    let mut track_map = resolve_track_ids(ids.clone(), db).await;
+
+    let mut wanted: Vec<String> = artists.iter().map(|name| normalize(name)).collect();
+    wanted.sort();
+    wanted.dedup();
+    let credited = |track: &TrackRead| {
+        let mut names: Vec<String> = track.artists.iter().map(|a| normalize(&a.name)).collect();
+        names.sort();
+        names.dedup();
+        names
+    };
+
+    let exact = ids.iter().find(|id| credited(&track_map[*id]) == wanted);
+    let partial = ids.iter().find(|id| {
+        let names = credited(&track_map[*id]);
+        wanted.iter().all(|name| names.contains(name))
+    });
+    match exact.or(partial) {
+        Some(id) => Ok(track_map.remove(id).expect("Resolved above")),
+        None => Err(MalojaError::TrackTitleNotFound { title: title.to_string() }),
+    }
+}
@@ -4,10 +4,12 @@ pub mod stats;
 pub mod info;
 pub mod scrobbles;
 pub mod history;
+pub mod api_keys;
 
 pub use get_or_create::*;
 pub use resolve::*;
 pub use stats::*;
 pub use info::*;
 pub use scrobbles::*;
-pub use history::*;
\ No newline at end of file
+pub use history::*;
+pub use api_keys::*;
\ No newline at end of file
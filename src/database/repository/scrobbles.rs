@@ -1,18 +1,57 @@
-use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait};
+use log::warn;
+use sea_orm::{ActiveModelTrait, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait, Select, TransactionTrait};
+use sea_orm::ActiveValue::Set;
 use sea_query::JoinType;
-use crate::database::connect;
+use crate::database::{mark_scrobble_write, WRITE_LOCK};
 use crate::database::errors::MalojaError;
-use crate::database::repository::resolve_track_ids;
-use crate::entity::scrobble::{ScrobbleRead, Entity as ScrobbleEntity, Column as ScrobbleColumn, Relation as ScrobbleRelation, Model as ScrobbleModel};
-use crate::entity::track::{TrackRead, Entity as TrackEntity, Column as TrackColumn, Relation as TrackRelation};
+use crate::database::repository::{create_scrobbles, get_or_create_tracks, resolve_track_ids};
+use crate::database::views::ReparseResult;
+use crate::entity::scrobble::{ScrobbleRead, Entity as ScrobbleEntity, Column as ScrobbleColumn, Relation as ScrobbleRelation, Model as ScrobbleModel, ActiveModel as ScrobbleActiveModel, ScrobbleWrite, RawScrobble};
+use crate::entity::track::{Column as TrackColumn, Relation as TrackRelation, TrackWrite};
 use crate::entity::track_artist::{Column as TrackArtistColumn};
+use crate::configuration::TIMEZONE;
 use crate::timeranges::TimeRange;
 
-pub async fn scrobbles(timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>, track_id: Option<u32>, new_to_old: bool) -> Result<Vec<ScrobbleRead>, MalojaError> {
-    assert!(
-        (artist_id.is_none() && album_id.is_none()) || (artist_id.is_none() && track_id.is_none()) || (track_id.is_none() && album_id.is_none())
-    );
-    let db = connect().await?;
+pub async fn scrobbles(timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>, track_id: Option<u32>, new_to_old: bool, db: &DatabaseConnection) -> Result<Vec<ScrobbleRead>, MalojaError> {
+    let result = scrobbles_with_models(timerange, artist_id, album_id, track_id, new_to_old, db).await?;
+    Ok(result.into_iter().map(|(_, scrobble)| scrobble).collect())
+}
+
+/// Timestamps of the first and last scrobble, or nothing if there are no scrobbles yet
+pub async fn scrobble_span(db: &DatabaseConnection) -> Result<Option<(i64, i64)>, MalojaError> {
+    let span: Option<(Option<i64>, Option<i64>)> = ScrobbleEntity::find()
+        .select_only()
+        .column_as(ScrobbleColumn::Timestamp.min(), "first")
+        .column_as(ScrobbleColumn::Timestamp.max(), "last")
+        .into_tuple()
+        .one(db)
+        .await?;
+    Ok(match span {
+        Some((Some(first), Some(last))) => Some((first, last)),
+        _ => None,
+    })
+}
+
+/// Same as `scrobbles`, but also returns the database models, which have the information that isn't shown publicly
+pub async fn scrobbles_with_models(timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>, track_id: Option<u32>, new_to_old: bool, db: &DatabaseConnection) -> Result<Vec<(ScrobbleModel, ScrobbleRead)>, MalojaError> {
+    let mut query = scrobble_query(timerange, artist_id, album_id, track_id)?;
+    if new_to_old {
+        query = query.order_by_desc(ScrobbleColumn::Timestamp).order_by_desc(ScrobbleColumn::Id);
+    }
+    else {
+        query = query.order_by_asc(ScrobbleColumn::Timestamp).order_by_asc(ScrobbleColumn::Id);
+    }
+    
+    let result: Vec<ScrobbleModel> = query.all(db).await?;
+    let resolved = resolve_scrobbles(result.clone(), db).await;
+    Ok(result.into_iter().zip(resolved).collect())
+}
+
+/// Scrobbles in the time range, limited to at most one of artist, album and track
+fn scrobble_query(timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>, track_id: Option<u32>) -> Result<Select<ScrobbleEntity>, MalojaError> {
+    if [artist_id, album_id, track_id].iter().filter(|id| id.is_some()).count() > 1 {
+        return Err(MalojaError::ParseError { message: "Scrobbles can only be limited to one artist, album or track".to_string() });
+    }
     let (from_ts, to_ts) = timerange.timestamp_boundaries();
     let mut query = ScrobbleEntity::find()
         .filter(ScrobbleColumn::Timestamp.between(from_ts, to_ts));
@@ -31,31 +70,141 @@ pub async fn scrobbles(timerange: TimeRange, artist_id: Option<u32>, album_id: O
         query = query
             .filter(ScrobbleColumn::TrackId.eq(track_id));
     };
+    Ok(query)
+}
 
-    if new_to_old {
-        query = query.order_by_desc(ScrobbleColumn::Timestamp);
+/// Creates the scrobbles and returns them in the order they were submitted
+pub async fn submit_scrobbles(input: Vec<ScrobbleWrite>, fail_on_existing: bool, db: &DatabaseConnection) -> Result<Vec<ScrobbleRead>, MalojaError> {
+    let created = create_scrobbles(input.clone(), fail_on_existing, db).await?;
+    let models = input.iter().map(|write| created[write].clone()).collect();
+    Ok(resolve_scrobbles(models, db).await)
+}
+
+const REPARSE_BATCH_SIZE: u64 = 1000;
+
+/// Parses all scrobbles again from their raw scrobble and moves them to the resulting track if it changed.
+/// Timestamp, origin and listen duration are kept as they are
+pub async fn reparse_scrobbles(parse: fn(&RawScrobble) -> Result<ScrobbleWrite, MalojaError>, db: &DatabaseConnection) -> Result<ReparseResult, MalojaError> {
+    let _lock = WRITE_LOCK.lock().await;
+    let mut result = ReparseResult::default();
+    let outcome = reparse_batches(parse, &mut result, db).await;
+    // batches before an error are changed already
+    if result.changed > 0 || result.removed > 0 {
+        mark_scrobble_write(db).await;
     }
-    else {
-        query = query.order_by_asc(ScrobbleColumn::Timestamp);
+    outcome.map(|_| result)
+}
+
+async fn reparse_batches(parse: fn(&RawScrobble) -> Result<ScrobbleWrite, MalojaError>, result: &mut ReparseResult, db: &DatabaseConnection) -> Result<(), MalojaError> {
+    let mut last_id = 0;
+    loop {
+        let batch: Vec<ScrobbleModel> = ScrobbleEntity::find()
+            .filter(ScrobbleColumn::Id.gt(last_id))
+            .order_by_asc(ScrobbleColumn::Id)
+            .limit(REPARSE_BATCH_SIZE)
+            .all(db).await?;
+        let Some(last) = batch.last() else { break };
+        last_id = last.id;
+        result.total += batch.len() as u32;
+
+        let mut parsed = vec![];
+        for model in batch {
+            // scrobbles from before raw scrobbles were stored only have an empty placeholder
+            let Ok(raw) = serde_json::from_value::<RawScrobble>(model.raw_scrobble.clone()) else {
+                result.skipped += 1;
+                continue;
+            };
+            match parse(&raw) {
+                Ok(write) => parsed.push((model, write.track)),
+                Err(e) => {
+                    warn!("Could not reparse scrobble {}: {}", model.id, e);
+                    result.failed += 1;
+                }
+            }
+        }
+        if parsed.is_empty() {
+            continue;
+        }
+
+        // a batch is moved completely or not at all, including the tracks it created
+        let transaction = db.begin().await?;
+        match move_to_tracks(parsed, &transaction).await {
+            Ok((changed, removed)) => {
+                transaction.commit().await?;
+                result.changed += changed;
+                result.removed += removed;
+            }
+            Err(e) => {
+                transaction.rollback().await?;
+                return Err(e);
+            }
+        }
     }
-    
-    let result: Vec<ScrobbleModel> = query.all(&db).await?;
-    let track_ids = result.iter().map(|s| s.track_id.clone()).collect();
-    let track_map = resolve_track_ids(track_ids, &db).await;
+    Ok(())
+}
+
+/// Moves the scrobbles to their newly parsed tracks, and returns how many were changed and removed
+async fn move_to_tracks(parsed: Vec<(ScrobbleModel, TrackWrite)>, db: &impl ConnectionTrait) -> Result<(u32, u32), MalojaError> {
+    let (mut changed, mut removed) = (0, 0);
+    let track_map = get_or_create_tracks(parsed.iter().map(|(_, track)| track.clone()).collect(), db).await?;
+    for (model, track) in parsed {
+        let track_id = track_map[&track].id;
+        if track_id == model.track_id {
+            continue;
+        }
+        // the same track in the same second is the same scrobble, so this one is a duplicate now
+        let existing = ScrobbleEntity::find()
+            .filter(ScrobbleColumn::Timestamp.eq(model.timestamp))
+            .filter(ScrobbleColumn::TrackId.eq(track_id))
+            .one(db).await?;
+        if existing.is_some() {
+            ScrobbleEntity::delete_by_id(model.id).exec(db).await?;
+            removed += 1;
+        } else {
+            let mut active: ScrobbleActiveModel = model.into();
+            active.track_id = Set(track_id);
+            active.update(db).await?;
+            changed += 1;
+        }
+    }
+    Ok((changed, removed))
+}
+
+/// Same as `scrobbles_with_models` in chronological order, but only the next batch after the given timestamp and id
+pub async fn scrobble_batch(timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>, track_id: Option<u32>, after: Option<(i64, u32)>, limit: u64, db: &DatabaseConnection) -> Result<Vec<(ScrobbleModel, ScrobbleRead)>, MalojaError> {
+    let mut query = scrobble_query(timerange, artist_id, album_id, track_id)?;
+    if let Some((timestamp, id)) = after {
+        query = query.filter(
+            Condition::any()
+                .add(ScrobbleColumn::Timestamp.gt(timestamp))
+                .add(ScrobbleColumn::Timestamp.eq(timestamp).and(ScrobbleColumn::Id.gt(id)))
+        );
+    }
+    let models: Vec<ScrobbleModel> = query
+        .order_by_asc(ScrobbleColumn::Timestamp)
+        .order_by_asc(ScrobbleColumn::Id)
+        .limit(limit)
+        .all(db).await?;
 
+    let resolved = resolve_scrobbles(models.clone(), db).await;
+    Ok(models.into_iter().zip(resolved).collect())
+}
 
-    let result = result.into_iter().map(|s| {
-        let tz = chrono_tz::Tz::Europe__Vienna; //TODO
-        let time = chrono::DateTime::from_timestamp(s.timestamp.clone(), 0).unwrap();
+async fn resolve_scrobbles(models: Vec<ScrobbleModel>, db: &DatabaseConnection) -> Vec<ScrobbleRead> {
+    let track_ids = models.iter().map(|s| s.track_id).collect();
+    let track_map = resolve_track_ids(track_ids, db).await;
+
+    models.into_iter().map(|s| {
+        let tz = *TIMEZONE;
+        let time = chrono::DateTime::from_timestamp(s.timestamp, 0).unwrap();
         let fmt = "%d. %b %Y %H:%M %Z";
         let local_time = time.with_timezone(&tz);
-        
+
         ScrobbleRead {
-            timestamp: s.timestamp.clone(),
+            id: s.id,
+            timestamp: s.timestamp,
             time_local: local_time.format(fmt).to_string(),
             track: track_map[&s.track_id].clone(),
         }
-    }).collect();
-
-    Ok(result)
-}
\ No newline at end of file
+    }).collect()
+}
@@ -1,6 +1,5 @@
-use sea_orm::{ColumnTrait, DbErr, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, JoinType, QueryFilter, QueryOrder, QuerySelect, RelationTrait};
 use sea_query::Expr;
-use crate::database::connect;
 use crate::database::errors::MalojaError;
 use crate::database::repository::{resolve_artist_ids, resolve_track_ids, resolve_album_ids};
 use crate::database::views::ChartsEntry;
@@ -19,8 +18,7 @@ use crate::timeranges::TimeRange;
 
 
 
-pub async fn charts_tracks(timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>) -> Result<Vec<ChartsEntry<TrackRead>>, MalojaError> {
-    let db = connect().await?;
+pub async fn charts_tracks(timerange: TimeRange, artist_id: Option<u32>, album_id: Option<u32>, db: &DatabaseConnection) -> Result<Vec<ChartsEntry<TrackRead>>, MalojaError> {
     let (from_ts, to_ts) = timerange.timestamp_boundaries();
     let mut query = Track::find()
         .select_only()
@@ -47,10 +45,10 @@ pub async fn charts_tracks(timerange: TimeRange, artist_id: Option<u32>, album_i
         .filter(ScrobbleColumn::Timestamp.between(from_ts, to_ts))
         .order_by_desc(ScrobbleColumn::Timestamp.count());
     
-    let result: Vec<(u32, u32, u32)> = query.into_tuple().all(&db).await?;
+    let result: Vec<(u32, u32, u32)> = query.into_tuple().all(db).await?;
 
     let id_list = result.iter().map(|(id, scrobbles, rank)| id.to_owned()).collect();
-    let id_map = resolve_track_ids(id_list, &db).await;
+    let id_map = resolve_track_ids(id_list, db).await;
 
     let charts: Vec<ChartsEntry<TrackRead>> = result.into_iter().map(|(id, scrobbles, rank)| {
         ChartsEntry {
@@ -63,8 +61,7 @@ pub async fn charts_tracks(timerange: TimeRange, artist_id: Option<u32>, album_i
     Ok(charts)
 }
 
-pub async fn charts_artists(timerange: TimeRange) -> Result<Vec<ChartsEntry<ArtistRead>>, MalojaError> {
-    let db = connect().await?;
+pub async fn charts_artists(timerange: TimeRange, db: &DatabaseConnection) -> Result<Vec<ChartsEntry<ArtistRead>>, MalojaError> {
     let (from_ts, to_ts) = timerange.timestamp_boundaries();
     let mut query = Artist::find()
         .select_only()
@@ -81,10 +78,10 @@ pub async fn charts_artists(timerange: TimeRange) -> Result<Vec<ChartsEntry<Arti
         .group_by(ArtistColumn::Id)
         .order_by_desc(ScrobbleColumn::Timestamp.count());
     
-    let result: Vec<(u32, u32, u32)> = query.into_tuple().all(&db).await?;
+    let result: Vec<(u32, u32, u32)> = query.into_tuple().all(db).await?;
 
     let id_list = result.iter().map(|(id, scrobbles, rank)| id.to_owned()).collect();
-    let id_map = resolve_artist_ids(id_list, &db).await;
+    let id_map = resolve_artist_ids(id_list, db).await;
 
     let charts: Vec<ChartsEntry<ArtistRead>> = result.into_iter().map(|(id, scrobbles, rank)| {
         ChartsEntry {
@@ -97,8 +94,7 @@ pub async fn charts_artists(timerange: TimeRange) -> Result<Vec<ChartsEntry<Arti
     Ok(charts)
 }
 
-pub async fn charts_albums(timerange: TimeRange, artist_id: Option<u32>) -> Result<Vec<ChartsEntry<AlbumRead>>, MalojaError> {
-    let db = connect().await?;
+pub async fn charts_albums(timerange: TimeRange, artist_id: Option<u32>, db: &DatabaseConnection) -> Result<Vec<ChartsEntry<AlbumRead>>, MalojaError> {
     let (from_ts, to_ts) = timerange.timestamp_boundaries();
     let mut query = Album::find()
         .select_only()
@@ -122,10 +118,10 @@ pub async fn charts_albums(timerange: TimeRange, artist_id: Option<u32>) -> Resu
     query = query
         .order_by_desc(ScrobbleColumn::Timestamp.count());
     
-    let result: Vec<(u32, u32, u32)> = query.into_tuple().all(&db).await?;
+    let result: Vec<(u32, u32, u32)> = query.into_tuple().all(db).await?;
 
     let id_list = result.iter().map(|(id, scrobbles, rank)| id.to_owned()).collect();
-    let id_map = resolve_album_ids(id_list, &db).await;
+    let id_map = resolve_album_ids(id_list, db).await;
 
     let charts: Vec<ChartsEntry<AlbumRead>> = result.into_iter().map(|(id, scrobbles, rank)| {
         ChartsEntry {
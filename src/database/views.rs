@@ -1,5 +1,6 @@
 // These are essentially the API schemas, but we store them here because they will also be used internally
 
+use std::collections::BTreeMap;
 use std::time::Duration;
 use serde::Serialize;
 use utoipa::ToSchema;
@@ -10,8 +11,37 @@ pub struct Charts<T: Clone> {
     #[schema(inline)]
     pub pagination: PaginationInfo,
     #[schema(inline)]
+    pub time_range: TimeRangeInfo,
+    #[schema(inline)]
     pub result: Vec<ChartsEntry<T>>
 }
+
+/// The requested time range and its neighbours, which are given as query parameters so they can be requested directly
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct TimeRangeInfo {
+    #[schema(examples("October 2021 to December 2021"))]
+    pub description: String,
+    #[schema(examples("from=2021/10&to=2021/12"))]
+    pub query: String,
+    /// A range of the same length right before, if there is one
+    #[schema(examples("from=2021/7&to=2021/9"))]
+    pub previous: Option<String>,
+    /// A range of the same length right after, unless it would be entirely in the future
+    #[schema(examples("from=2022/1&to=2022/3"))]
+    pub next: Option<String>,
+}
+
+impl TimeRangeInfo {
+    pub fn new(timerange: &TimeRange) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        TimeRangeInfo {
+            description: timerange.to_string(),
+            query: timerange.to_query(),
+            previous: timerange.previous().map(|previous| previous.to_query()),
+            next: timerange.next().filter(|next| next.timestamp_boundaries().0 <= now).map(|next| next.to_query()),
+        }
+    }
+}
 #[derive(Serialize, ToSchema)]
 pub struct Top<T: Clone> {
     #[schema(inline)]
@@ -73,4 +103,77 @@ pub struct PerformanceEntry {
     pub time_range: TimeRange,
     #[schema(examples(3))]
     pub rank: u32,
-}
\ No newline at end of file
+}
+#[derive(Serialize, ToSchema, Clone, Debug, Default)]
+pub struct ReparseResult {
+    /// Scrobbles that were looked at
+    #[schema(examples(24873))]
+    pub total: u32,
+    /// Scrobbles that are now assigned to a different track
+    #[schema(examples(112))]
+    pub changed: u32,
+    /// Scrobbles that would now be a second scrobble of the same track at the same time, and were removed as duplicates
+    #[schema(examples(2))]
+    pub removed: u32,
+    /// Scrobbles without a stored raw scrobble, e.g. from before raw scrobbles were kept
+    #[schema(examples(3100))]
+    pub skipped: u32,
+    /// Scrobbles whose raw scrobble could not be parsed
+    #[schema(examples(0))]
+    pub failed: u32,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportState {
+    #[default]
+    Running,
+    Finished,
+    Failed,
+}
+#[derive(Serialize, ToSchema, Clone, Debug, Default)]
+pub struct ImportReport {
+    /// Name of the imported file in the import folder
+    #[schema(examples("maloja_export.json"))]
+    pub file: String,
+    #[schema(examples("Maloja export"))]
+    pub format: String,
+    pub state: ImportState,
+    /// UNIX timestamps of when the import started and finished
+    #[schema(examples(1729250000))]
+    pub started: i64,
+    #[schema(examples(1729250420))]
+    pub finished: Option<i64>,
+    /// Records that were read as valid scrobbles
+    #[schema(examples(301234))]
+    pub parsed: u32,
+    /// Scrobbles that were new
+    #[schema(examples(299000))]
+    pub created: u32,
+    /// Scrobbles that already existed, or appeared in the file several times
+    #[schema(examples(2234))]
+    pub duplicate: u32,
+    /// Records that could not be read as scrobbles, or could not be written to the database
+    #[schema(examples(12))]
+    pub rejected: u32,
+    /// How many records were rejected for each reason
+    pub rejection_reasons: BTreeMap<String, u32>,
+    /// Why the whole file could not be imported
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct BackupInfo {
+    /// Name of the file in the backup folder
+    #[schema(examples("maloja_20241018_030000_scheduled.sqlite"))]
+    pub file: String,
+    /// Size in bytes
+    #[schema(examples(52428800))]
+    pub size: u64,
+    /// UNIX timestamp of when the backup was written
+    #[schema(examples(1729220400))]
+    pub created: i64,
+    /// Scheduled backups are deleted once there are more than the configured amount, all others are kept
+    #[schema(examples(true))]
+    pub scheduled: bool,
+}
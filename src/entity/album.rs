@@ -1,5 +1,5 @@
 use sea_orm::entity::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use super::artist::{ArtistRead, ArtistReadContext, ArtistWrite};
 
@@ -48,7 +48,7 @@ impl ActiveModelBehavior for ActiveModel {}
 /// Representation of an album with the information that can be supplied from the outside.
 /// Used for creating or patching an album, or to identify an album within another entity which could
 /// exist or should be newly created
-#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, ToSchema)]
+#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, Deserialize, ToSchema)]
 pub struct AlbumWrite {
     #[schema(minimum = 1)]
     pub id: Option<u32>,
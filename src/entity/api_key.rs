@@ -0,0 +1,84 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "api_keys")]
+pub struct Model {
+
+    #[sea_orm(primary_key)]
+    pub id: u32,
+
+    /// The token clients authenticate with
+    #[sea_orm(unique)]
+    pub key: String,
+
+    /// Shared secret for protocols that sign their requests (Audioscrobbler)
+    pub secret: String,
+
+    /// Name to identify the client or person using this key
+    pub name: String,
+
+    pub description: Option<String>,
+
+    /// Unix timestamp of the key creation
+    pub created: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::session::Entity")]
+    Session,
+}
+
+impl Related<super::session::Entity> for Entity {
+    fn to() -> RelationDef { Relation::Session.def() }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+/// Representation of an API key with the information that can be supplied from the outside.
+/// If no key is supplied, a random one is generated
+#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyWrite {
+    #[schema(examples("Navidrome"))]
+    pub name: String,
+    #[schema(examples("Scrobbles from the living room server"))]
+    pub description: Option<String>,
+    /// Only needed to keep existing keys working, e.g. when migrating clients from another server
+    pub key: Option<String>,
+    /// Separate value that Audioscrobbler clients use to sign their requests, it is never sent itself.
+    /// A random secret is generated if none is given, which then has to be entered in the client
+    pub secret: Option<String>,
+}
+
+/// Representation of an API key as it should be shown to the outside. Only ever shown to admins
+#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, ToSchema)]
+#[schema(title = "API Key", as = entity::api_key::ApiKeyRead, description = "Credential for a client that is allowed to submit scrobbles")]
+pub struct ApiKeyRead {
+    #[schema(minimum = 1)]
+    pub id: u32,
+    #[schema(examples("FDYrVjh8r3Yt7uPWbIzGr9ajJXGSlCwvVstqmcuhxAD4Px2D7Ajbq4UvbuRDnRUJ"))]
+    pub key: String,
+    #[schema(examples("d8Vv0lXyZaoGMAq5UGL3C6NoDr4RYRbj"))]
+    pub secret: String,
+    #[schema(examples("Navidrome"))]
+    pub name: String,
+    #[schema(examples("Scrobbles from the living room server"))]
+    pub description: Option<String>,
+    #[schema(examples(1735689600))]
+    pub created: i64,
+}
+
+impl From<Model> for ApiKeyRead {
+    fn from(model: Model) -> Self {
+        ApiKeyRead {
+            id: model.id,
+            key: model.key,
+            secret: model.secret,
+            name: model.name,
+            description: model.description,
+            created: model.created,
+        }
+    }
+}
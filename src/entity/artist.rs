@@ -1,6 +1,6 @@
 use sea_orm::ActiveValue::Set;
 use sea_orm::entity::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 #[derive(Debug, Clone, DeriveEntityModel, Serialize)]
@@ -46,7 +46,7 @@ impl ActiveModelBehavior for ActiveModel {}
 /// Representation of an artist with the information that can be supplied from the outside.
 /// Used for creating or patching an artist, or to identify an artist within another entity who could
 /// exist or should be newly created
-#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, ToSchema)]
+#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, Deserialize, ToSchema)]
 pub struct ArtistWrite {
     #[schema(minimum = 1)]
     pub id: Option<u32>,
@@ -4,3 +4,5 @@ pub mod scrobble;
 pub mod track;
 pub mod album_artist;
 pub mod track_artist;
+pub mod api_key;
+pub mod session;
@@ -1,16 +1,20 @@
 use crate::entity::track::TrackRead;
+use std::hash::{Hash, Hasher};
 use std::time::Duration;
 use sea_orm::entity::prelude::*;
 use sea_orm::prelude::Json;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use crate::entity::track::TrackWrite;
 
 #[derive(Debug, Clone, DeriveEntityModel, Serialize)]
 #[sea_orm(table_name = "scrobbles")]
 pub struct Model {
-    
-    #[sea_orm(primary_key, auto_increment = false)]
+
+    #[sea_orm(primary_key)]
+    pub id: u32,
+
+    /// Not unique on its own, a scrobble is identified by timestamp and track together
     pub timestamp: i64,
 
     pub track_id: u32,
@@ -39,7 +43,7 @@ impl ActiveModelBehavior for ActiveModel {}
 
 /// Representation of a scrobble with the information that can be supplied from the outside.
 /// Used for creating or patching a scrobble
-#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, ToSchema)]
+#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, Deserialize, ToSchema)]
 pub struct ScrobbleWrite {
     #[schema(examples(904098042))]
     pub timestamp: i64,
@@ -48,11 +52,55 @@ pub struct ScrobbleWrite {
     pub origin: Option<String>,
     #[schema(examples(174))]
     pub listen_duration: Option<u32>,
+    /// Set by whoever parsed the scrobble, never supplied from the outside
+    #[serde(skip)]
+    pub raw_scrobble: Option<RawScrobble>,
+}
+
+/// Protocol or file format a scrobble was submitted in, which decides how its raw payload is parsed
+#[derive(Clone, Copy, Eq, Hash, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrobbleSource {
+    Listenbrainz,
+    Audioscrobbler,
+    AudioscrobblerLegacy,
+    MalojaV1,
+    MalojaV2,
+    MalojaExport,
+    MalojaLegacy,
+    LastfmCsv,
+    LastfmJson,
+    SpotifyExtended,
+    SpotifyBasic,
+    ScrobblerLog,
+}
+
+/// A scrobble exactly as it was submitted, so it can be parsed again once the parsing rules improve
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub struct RawScrobble {
+    pub source: ScrobbleSource,
+    pub payload: Json,
+}
+
+impl RawScrobble {
+    pub fn new(source: ScrobbleSource, payload: Json) -> Self {
+        RawScrobble { source, payload }
+    }
+}
+
+// json values can't be hashed, but their serialization can
+impl Hash for RawScrobble {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+        self.payload.to_string().hash(state);
+    }
 }
 
 #[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, ToSchema)]
 #[schema(title = "Scrobble", as = entity::scrobble::ScrobbleRead, description = "Instance of user listening to a track")]
 pub struct ScrobbleRead {
+    #[schema(minimum = 1)]
+    pub id: u32,
     #[schema(examples(904098042))]
     pub timestamp: i64,
     pub time_local: String, //TODO generate in template instead?
@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+use serde::Serialize;
+
+#[derive(Debug, Clone, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "sessions")]
+pub struct Model {
+
+    #[sea_orm(primary_key)]
+    pub id: u32,
+
+    /// Session key that clients use for requests after the initial authentication
+    #[sea_orm(unique)]
+    pub session_key: String,
+
+    /// API key that was used to authenticate the session
+    pub api_key_id: u32,
+
+    /// Unix timestamp of the session creation
+    pub created: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(belongs_to = "super::api_key::Entity", from = "Column::ApiKeyId", to = "super::api_key::Column::Id")]
+    ApiKey,
+}
+
+impl Related<super::api_key::Entity> for Entity {
+    fn to() -> RelationDef { Relation::ApiKey.def() }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
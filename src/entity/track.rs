@@ -1,5 +1,5 @@
 use sea_orm::entity::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use super::artist::{ArtistRead, ArtistReadContext, ArtistWrite};
 use super::album::{AlbumRead, AlbumWrite};
@@ -62,7 +62,7 @@ impl ActiveModelBehavior for ActiveModel {}
 /// Representation of a track with the information that can be supplied from the outside.
 /// Used for creating or patching a track, or to identify a track within another entity which could
 /// exist or should be newly created
-#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, ToSchema)]
+#[derive(Clone, Eq, Hash, PartialEq, Debug, Serialize, Deserialize, ToSchema)]
 pub struct TrackWrite {
     #[schema(minimum = 1)]
     pub id: Option<u32>,
@@ -9,14 +9,92 @@ mod timeranges;
 mod uri;
 
 use crate::configuration::logging::{display_path, display_url};
+use crate::database::export::{Export, ExportFormat};
+use crate::database::views::ImportState;
+use crate::uri::QueryTimerange;
+use clap::{Parser, Subcommand};
 use colored::Colorize;
-use log::{debug, info, warn};
+use log::{error, info};
 use std::io;
 use std::io::Write;
+use std::path::PathBuf;
+use std::sync::LazyLock;
 use tokio::time::{sleep, Duration};
 
+/// Without a command, the server is started. All other commands do their job and exit
+#[derive(Parser)]
+#[command(name = "maloja", version, about = "Self-hosted music scrobble database")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the server
+    Serve,
+    /// Import scrobbles from files in any of the supported formats
+    Import {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Export all scrobbles, or only those of a time range, artist, album or track
+    Export {
+        /// maloja, csv or listenbrainz
+        #[arg(short, long, default_value = "maloja")]
+        format: ExportFormat,
+        /// Time range like 2024, 2024/03, 2024w10, thismonth or last30days
+        #[arg(long, conflicts_with_all = ["from", "to"])]
+        during: Option<String>,
+        /// Start of the exported scrobbles, as time range, UNIX timestamp or ISO 8601 datetime
+        #[arg(long)]
+        from: Option<String>,
+        /// End of the exported scrobbles, same as from
+        #[arg(long)]
+        to: Option<String>,
+        /// ID of the artist
+        #[arg(long, conflicts_with_all = ["album", "track"])]
+        artist: Option<u32>,
+        /// ID of the album
+        #[arg(long, conflicts_with = "track")]
+        album: Option<u32>,
+        /// ID of the track
+        #[arg(long)]
+        track: Option<u32>,
+        /// Defaults to a new file in the export folder
+        file: Option<PathBuf>,
+    },
+    /// Write a copy of the database to the backup folder
+    Backup,
+    /// Replace the database with a backup. The current database is backed up first
+    Restore {
+        file: PathBuf,
+    },
+    /// Check the configuration file for errors
+    CheckConfig,
+    /// Print a configuration file with all options and their defaults
+    PrintConfigTemplate,
+    /// Fill the database with random scrobbles for development
+    GenerateFixture,
+}
+
 #[tokio::main]
 async fn main() {
+    let command = Cli::parse().command.unwrap_or(Command::Serve);
+
+    // these only look at the configuration, so their output shouldn't be mixed with anything else
+    match command {
+        Command::PrintConfigTemplate => {
+            print!("{}", configuration::config_template());
+            return;
+        }
+        Command::CheckConfig => {
+            check_config();
+            return;
+        }
+        _ => {}
+    }
+
     println!();
 
     // SETUP
@@ -25,24 +103,130 @@ async fn main() {
 
     configuration::logging::setup_logger().unwrap();
     debug_info();
+    LazyLock::force(&configuration::TIMEZONE);
+    if let Command::Serve = command {
+        LazyLock::force(&configuration::ADMIN_PASSWORD);
+    }
 
     // create files
     info!("Creating local files...");
     configuration::create_config_template().unwrap();
 
+    // the database can only be replaced while nothing is connected to it
+    if let Command::Restore { file } = &command {
+        match database::restore_database(file).await {
+            Ok(Some(previous)) => info!("Previous database backed up to {}", display_path(&previous)),
+            Ok(None) => {}
+            Err(e) => {
+                error!("Could not restore {}: {:?}", display_path(file), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // DATABASE
     info!("Initializing database...");
-    database::init_db().await;
+    let db = match database::init_db().await {
+        Ok(db) => db,
+        Err(e) => {
+            match e {
+                database::errors::MalojaError::DatabaseVersionTooNew { database, supported } => error!(
+                    "The database has schema version {}, but this version of Maloja only supports up to {}. Please upgrade Maloja.",
+                    database, supported
+                ),
+                e => error!("Could not initialize database: {:?}", e),
+            }
+            std::process::exit(1);
+        }
+    };
+
+    // COMMANDS
+    match command {
+        Command::Serve => serve(db).await,
+        Command::Import { files } => {
+            let mut failed = 0;
+            for file in files {
+                match database::import::import_path(&file, &db).await {
+                    Ok(report) if report.state == ImportState::Finished => {}
+                    Ok(_) => failed += 1,
+                    Err(e) => {
+                        error!("Could not import {}: {:?}", display_path(&file), e);
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::Export { format, during, from, to, artist, album, track, file } => {
+            let timerange = match &during {
+                Some(during) => QueryTimerange::match_during(during),
+                None => QueryTimerange::match_bounds(from.as_deref(), to.as_deref()),
+            };
+            let export = match timerange.and_then(|timerange| Export::new(format, timerange, artist, album, track)) {
+                Ok(export) => export,
+                Err(e) => {
+                    error!("Invalid export options: {:?}", e);
+                    std::process::exit(1);
+                }
+            };
+            let file = file.unwrap_or_else(|| database::export::default_export_path(format));
+            if let Err(e) = database::export::export_to_file(export, &file, &db).await {
+                error!("Export failed: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Backup => match database::backup_database(&db, "manual").await {
+            Ok(path) => info!("Database backed up to {}", display_path(&path)),
+            Err(e) => {
+                error!("Backup failed: {:?}", e);
+                std::process::exit(1);
+            }
+        },
+        Command::Restore { file } => info!("Restored database from {}", display_path(&file)),
+        Command::GenerateFixture => {
+            database::fixtures::fixture(&db).await;
+            info!("Added random scrobbles to the database");
+        }
+        Command::CheckConfig | Command::PrintConfigTemplate => unreachable!("Handled before setup"),
+    }
+}
+
+async fn serve(db: sea_orm::DatabaseConnection) {
+    // IMPORTS
+    // in the background, so their progress can already be checked through the API
+    info!("Checking imports...");
+    tokio::spawn(import_files(db.clone()));
+    tokio::spawn(database::backups::scheduled_backups(db.clone()));
 
     // SERVER
     info!("Starting up server...");
-    server::run_server().await;
+    server::run_server(db).await;
     
     info!("Shutting down...");
     sleep(Duration::from_secs(2)).await;
     io::stdout().flush().unwrap();
 }
 
+fn check_config() {
+    match configuration::check_config() {
+        Ok(path) if path.exists() => println!("Configuration file {} is valid", display_path(&path)),
+        Ok(path) => println!("There is no configuration file at {}, the defaults are used", display_path(&path)),
+        Err(e) => {
+            println!("Configuration is invalid: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn import_files(db: sea_orm::DatabaseConnection) {
+    match database::import::import(db).await {
+        Ok((imported, failed)) => info!("Imported {} files, failed {}.", imported, failed),
+        Err(e) => error!("Failed to check for imports: {}", e),
+    }
+}
+
 fn debug_info() {
     println!("{} v{}", "Maloja".yellow(), env!("CARGO_PKG_VERSION"));
     let folders = &configuration::FOLDERS;
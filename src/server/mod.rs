@@ -1,30 +1,64 @@
 mod pages;
 
+use axum::extract::{FromRef, Request, State};
+use axum::middleware::{self, Next};
 use axum::response::{Html, IntoResponse, Response};
 use axum::{Json, Router};
+use sea_orm::DatabaseConnection;
 use tower_http::services::{ServeDir, ServeFile};
 use axum::routing::get;
 use crate::api::mount_apis;
 use crate::configuration::CONFIG;
+use crate::database;
+use log::error;
 use pages::*;
 
-pub async fn run_server() {
+/// Shared resources that handlers can extract with `State`
+#[derive(Clone)]
+pub struct AppState {
+    pub db: DatabaseConnection,
+}
+
+impl FromRef<AppState> for DatabaseConnection {
+    fn from_ref(state: &AppState) -> Self {
+        // only clones the handle, the pool itself is shared
+        state.db.clone()
+    }
+}
+
+/// All time reaches from the first to the last scrobble. They're loaded after every write, this only catches up
+/// if that failed
+async fn refresh_scrobble_span(State(db): State<DatabaseConnection>, request: Request, next: Next) -> Response {
+    if let Err(e) = database::refresh_scrobble_span(&db).await {
+        error!("Could not load the first and last scrobble: {:?}", e);
+    }
+    next.run(request).await
+}
+
+pub async fn run_server(db: DatabaseConnection) {
     // TODO: files in package
 
+    let state = AppState { db };
     let mut app = Router::new();
     // APIS
-    app = mount_apis(app);
+    app = mount_apis(app, state.clone());
     // SPECIAL PATHS
     app = app
         .nest_service("/api_explorer", ServeFile::new("src/web/special/api_explorer.html"));
     // TEMPLATES
     app = app
         .route("/about", get(about))
+        .route("/charts", get(charts))
+        .route("/charts_artists", get(charts_artists))
+        .route("/charts_tracks", get(charts_tracks))
+        .route("/charts_albums", get(charts_albums))
         .route("/artist/{id}", get(info_artist))
         .route("/track/{id}", get(info_track))
         .route("/album/{id}", get(info_album));
     // STATIC FILES
     app = app.fallback_service(ServeDir::new("src/web/static"));
+    app = app.layer(middleware::from_fn_with_state(state.clone(), refresh_scrobble_span));
+    let app = app.with_state(state);
 
     let bind_address = format!("{}:{}", CONFIG.bind_address, CONFIG.port);
     let listener = tokio::net::TcpListener::bind(bind_address).await.unwrap();
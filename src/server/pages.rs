@@ -1,16 +1,18 @@
-use axum::extract::Path;
+use axum::extract::{Path, Query, State};
 use axum::response::{Html, IntoResponse, Response};
 //use dynja::Template;
 use askama::Template;
 use dynja::minijinja::functions::range;
+use sea_orm::DatabaseConnection;
 use crate::database;
-use crate::database::views::{ChartsEntry, PerformanceEntry, PulseEntry};
+use crate::database::errors::MalojaError;
+use crate::database::views::{ChartsEntry, PerformanceEntry, PulseEntry, TimeRangeInfo};
 use crate::entity::album::AlbumRead;
 use crate::entity::artist::ArtistRead;
 use crate::entity::scrobble::ScrobbleRead;
 use crate::entity::track::TrackRead;
 use crate::timeranges::{RangeType, TimeRange, ALL_TIME};
-use crate::uri::PathEntity;
+use crate::uri::{PathEntity, QueryTimerange};
 
 
 /*
@@ -51,27 +53,27 @@ struct ArtistPage {
     pulses: Vec<(RangeType, Vec<PulseEntry>)>,
     performances: Vec<(RangeType, Vec<PerformanceEntry>)>,
 }
-pub async fn info_artist(Path(params_path): Path<PathEntity>) -> Response {
-    let result = database::repository::artist_info(params_path.id).await.unwrap();
-    let tracks = database::repository::charts_tracks(ALL_TIME, Some(result.id), None).await.unwrap();
-    let scrobbles = database::repository::scrobbles(ALL_TIME, Some(result.id), None, None, true).await.unwrap();
+pub async fn info_artist(State(db): State<DatabaseConnection>, Path(params_path): Path<PathEntity>) -> Response {
+    let result = database::repository::artist_info(params_path.id, &db).await.unwrap();
+    let tracks = database::repository::charts_tracks(ALL_TIME, Some(result.id), None, &db).await.unwrap();
+    let scrobbles = database::repository::scrobbles(ALL_TIME, Some(result.id), None, None, true, &db).await.unwrap();
 
 
     let range_types_and_ranges = get_last_ranges(12);
     // async closures unstable
     /*let pulses = range_types_and_ranges.iter().map(async |range_type, ranges| {
-        (range_type, database::repository::pulse(ranges.clone(), Some(result.id), None, None).await.unwrap())
+        (range_type, database::repository::pulse(ranges.clone(), Some(result.id), None, None, &db).await.unwrap())
     }).collect();
     let performances = range_types_and_ranges.iter().map(async |range_type, ranges| {
-        (range_type, database::repository::performance(ranges, Some(result.id), None, None).await.unwrap())
+        (range_type, database::repository::performance(ranges, Some(result.id), None, None, &db).await.unwrap())
     }).collect();*/
     let mut pulses = vec![];
     for (range_type, ranges) in &range_types_and_ranges {
-        pulses.push((range_type.clone(), database::repository::pulse(ranges.clone(), Some(result.id), None, None).await.unwrap()));
+        pulses.push((range_type.clone(), database::repository::pulse(ranges.clone(), Some(result.id), None, None, &db).await.unwrap()));
     }
     let mut performances = vec![];
     for (range_type, ranges) in &range_types_and_ranges {
-        performances.push((range_type.clone(), database::repository::performance(ranges.clone(), Some(result.id), None, None).await.unwrap()));
+        performances.push((range_type.clone(), database::repository::performance(ranges.clone(), Some(result.id), None, None, &db).await.unwrap()));
     }
 
     let p = ArtistPage {
@@ -92,18 +94,18 @@ struct TrackPage {
     pulses: Vec<(RangeType, Vec<PulseEntry>)>,
     performances: Vec<(RangeType, Vec<PerformanceEntry>)>,
 }
-pub async fn info_track(Path(params_path): Path<PathEntity>) -> Response {
-    let result = database::repository::track_info(params_path.id).await.unwrap();
-    let scrobbles = database::repository::scrobbles(ALL_TIME, None, None, Some(result.id), true).await.unwrap();
+pub async fn info_track(State(db): State<DatabaseConnection>, Path(params_path): Path<PathEntity>) -> Response {
+    let result = database::repository::track_info(params_path.id, &db).await.unwrap();
+    let scrobbles = database::repository::scrobbles(ALL_TIME, None, None, Some(result.id), true, &db).await.unwrap();
 
     let range_types_and_ranges = get_last_ranges(12);
     let mut pulses = vec![];
     for (range_type, ranges) in &range_types_and_ranges {
-        pulses.push((range_type.clone(), database::repository::pulse(ranges.clone(), None, None, Some(result.id)).await.unwrap()));
+        pulses.push((range_type.clone(), database::repository::pulse(ranges.clone(), None, None, Some(result.id), &db).await.unwrap()));
     }
     let mut performances = vec![];
     for (range_type, ranges) in &range_types_and_ranges {
-        performances.push((range_type.clone(), database::repository::performance(ranges.clone(), None, None, Some(result.id)).await.unwrap()));
+        performances.push((range_type.clone(), database::repository::performance(ranges.clone(), None, None, Some(result.id), &db).await.unwrap()));
     }
 
     let p = TrackPage {
@@ -124,19 +126,19 @@ struct AlbumPage {
     pulses: Vec<(RangeType, Vec<PulseEntry>)>,
     performances: Vec<(RangeType, Vec<PerformanceEntry>)>,
 }
-pub async fn info_album(Path(params_path): Path<PathEntity>) -> Response {
-    let result = database::repository::album_info(params_path.id).await.unwrap();
-    let tracks = database::repository::charts_tracks(ALL_TIME, None, Some(result.id)).await.unwrap();
-    let scrobbles = database::repository::scrobbles(ALL_TIME, None, Some(result.id), None, true).await.unwrap();
+pub async fn info_album(State(db): State<DatabaseConnection>, Path(params_path): Path<PathEntity>) -> Response {
+    let result = database::repository::album_info(params_path.id, &db).await.unwrap();
+    let tracks = database::repository::charts_tracks(ALL_TIME, None, Some(result.id), &db).await.unwrap();
+    let scrobbles = database::repository::scrobbles(ALL_TIME, None, Some(result.id), None, true, &db).await.unwrap();
 
     let range_types_and_ranges = get_last_ranges(12);
     let mut pulses = vec![];
     for (range_type, ranges) in &range_types_and_ranges {
-        pulses.push((range_type.clone(), database::repository::pulse(ranges.clone(), None, Some(result.id), None).await.unwrap()));
+        pulses.push((range_type.clone(), database::repository::pulse(ranges.clone(), None, Some(result.id), None, &db).await.unwrap()));
     }
     let mut performances = vec![];
     for (range_type, ranges) in &range_types_and_ranges {
-        performances.push((range_type.clone(), database::repository::performance(ranges.clone(), None, Some(result.id), None).await.unwrap()));
+        performances.push((range_type.clone(), database::repository::performance(ranges.clone(), None, Some(result.id), None, &db).await.unwrap()));
     }
 
     let p = AlbumPage {
@@ -151,6 +153,74 @@ pub async fn info_album(Path(params_path): Path<PathEntity>) -> Response {
 
 
 
+#[derive(Template)]
+#[template(path = "charts.html")]
+struct ChartsPage {
+    time_range: TimeRangeInfo,
+    artist_charts: Vec<ChartsEntry<ArtistRead>>,
+    track_charts: Vec<ChartsEntry<TrackRead>>,
+    album_charts: Vec<ChartsEntry<AlbumRead>>,
+}
+pub async fn charts(State(db): State<DatabaseConnection>, Query(params_time): Query<QueryTimerange>) -> Result<Response, MalojaError> {
+    let timerange = params_time.to_timerange()?;
+
+    let p = ChartsPage {
+        time_range: TimeRangeInfo::new(&timerange),
+        artist_charts: database::repository::charts_artists(timerange.clone(), &db).await?,
+        track_charts: database::repository::charts_tracks(timerange.clone(), None, None, &db).await?,
+        album_charts: database::repository::charts_albums(timerange, None, &db).await?,
+    };
+    Ok(Html(p.render().unwrap()).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "charts_artists.html")]
+struct ArtistChartsPage {
+    time_range: TimeRangeInfo,
+    artist_charts: Vec<ChartsEntry<ArtistRead>>,
+}
+pub async fn charts_artists(State(db): State<DatabaseConnection>, Query(params_time): Query<QueryTimerange>) -> Result<Response, MalojaError> {
+    let timerange = params_time.to_timerange()?;
+
+    let p = ArtistChartsPage {
+        time_range: TimeRangeInfo::new(&timerange),
+        artist_charts: database::repository::charts_artists(timerange, &db).await?,
+    };
+    Ok(Html(p.render().unwrap()).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "charts_tracks.html")]
+struct TrackChartsPage {
+    time_range: TimeRangeInfo,
+    track_charts: Vec<ChartsEntry<TrackRead>>,
+}
+pub async fn charts_tracks(State(db): State<DatabaseConnection>, Query(params_time): Query<QueryTimerange>) -> Result<Response, MalojaError> {
+    let timerange = params_time.to_timerange()?;
+
+    let p = TrackChartsPage {
+        time_range: TimeRangeInfo::new(&timerange),
+        track_charts: database::repository::charts_tracks(timerange, None, None, &db).await?,
+    };
+    Ok(Html(p.render().unwrap()).into_response())
+}
+
+#[derive(Template)]
+#[template(path = "charts_albums.html")]
+struct AlbumChartsPage {
+    time_range: TimeRangeInfo,
+    album_charts: Vec<ChartsEntry<AlbumRead>>,
+}
+pub async fn charts_albums(State(db): State<DatabaseConnection>, Query(params_time): Query<QueryTimerange>) -> Result<Response, MalojaError> {
+    let timerange = params_time.to_timerange()?;
+
+    let p = AlbumChartsPage {
+        time_range: TimeRangeInfo::new(&timerange),
+        album_charts: database::repository::charts_albums(timerange, None, &db).await?,
+    };
+    Ok(Html(p.render().unwrap()).into_response())
+}
+
 #[derive(Template)]
 #[template(path = "about.html")]
 struct AboutPage {
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use axum::body::to_bytes;
+use axum::http::StatusCode;
+use sea_orm::DatabaseConnection;
+use serde_json::Value;
+use crate::api::audioscrobbler::{dispatch, signature};
+use crate::database::repository::{create_api_key, create_session, scrobbles_with_models};
+use crate::entity::api_key::{ApiKeyRead, ApiKeyWrite};
+use crate::timeranges::ALL_TIME;
+use super::memory_database;
+
+async fn api_key(db: &DatabaseConnection) -> ApiKeyRead {
+    super::environment();
+    create_api_key(ApiKeyWrite { name: "test".to_string(), description: None, key: None, secret: None }, db).await.unwrap()
+}
+
+/// Sends a signed request in JSON format and returns status and response
+async fn request(params: &[(&str, &str)], api_key: &ApiKeyRead, db: &DatabaseConnection) -> (StatusCode, Value) {
+    let mut params: HashMap<String, String> = params.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect();
+    params.insert("api_key".to_string(), api_key.key.clone());
+    params.insert("api_sig".to_string(), signature(&params, &api_key.secret));
+    params.insert("format".to_string(), "json".to_string());
+    let response = dispatch(params, db).await;
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn mobile_session_with_key_as_password() {
+    let db = memory_database().await;
+    let api_key = api_key(&db).await;
+    let (status, response) = request(&[("method", "auth.getMobileSession"), ("username", "user"), ("password", &api_key.key)], &api_key, &db).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(response["session"]["key"].as_str().is_some_and(|key| !key.is_empty()));
+}
+
+#[tokio::test]
+async fn mobile_session_with_wrong_password() {
+    let db = memory_database().await;
+    let api_key = api_key(&db).await;
+    let (status, response) = request(&[("method", "auth.getMobileSession"), ("username", "user"), ("password", "wrong")], &api_key, &db).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(response["error"], 4);
+
+    let (_, response) = request(&[("method", "auth.getMobileSession"), ("username", "user"), ("authToken", "0123456789abcdef0123456789abcdef")], &api_key, &db).await;
+    assert_eq!(response["error"], 4);
+}
+
+#[tokio::test]
+async fn invalid_scrobbles_are_ignored_individually() {
+    let db = memory_database().await;
+    let api_key = api_key(&db).await;
+    let session_key = create_session(api_key.id, &db).await.unwrap();
+    let (status, response) = request(&[
+        ("method", "track.scrobble"), ("sk", &session_key),
+        ("artist[0]", "Twice"), ("track[0]", "Fancy"), ("timestamp[0]", "1633269900"),
+        ("artist[1]", "Twice"), ("timestamp[1]", "1633270100"),
+        ("artist[2]", "Blackpink"), ("track[2]", "Ice Cream"), ("timestamp[2]", "soon"),
+        ("artist[3]", "Blackpink"), ("track[3]", "Lovesick Girls"), ("timestamp[3]", "1633270300"),
+    ], &api_key, &db).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response["scrobbles"]["@attr"]["accepted"], "2");
+    assert_eq!(response["scrobbles"]["@attr"]["ignored"], "2");
+    let codes: Vec<&Value> = response["scrobbles"]["scrobble"].as_array().unwrap().iter()
+        .map(|scrobble| &scrobble["ignoredMessage"]["code"])
+        .collect();
+    assert_eq!(codes, ["0", "2", "3", "0"]);
+
+    let stored = scrobbles_with_models(ALL_TIME, None, None, None, false, &db).await.unwrap();
+    assert_eq!(stored.len(), 2);
+}
+
+#[tokio::test]
+async fn too_many_scrobbles() {
+    let db = memory_database().await;
+    let api_key = api_key(&db).await;
+    let session_key = create_session(api_key.id, &db).await.unwrap();
+    let mut params = vec![("method".to_string(), "track.scrobble".to_string()), ("sk".to_string(), session_key)];
+    for index in 0..51 {
+        params.push((format!("artist[{}]", index), "Twice".to_string()));
+        params.push((format!("track[{}]", index), "Fancy".to_string()));
+        params.push((format!("timestamp[{}]", index), (1633269900 + index * 300).to_string()));
+    }
+    let params: Vec<(&str, &str)> = params.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    let (status, response) = request(&params, &api_key, &db).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(response["error"], 6);
+
+    let stored = scrobbles_with_models(ALL_TIME, None, None, None, false, &db).await.unwrap();
+    assert!(stored.is_empty());
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use serde_json::Value;
+use crate::api::listenbrainz::parse_listen;
+use crate::database::errors::MalojaError;
+use sea_orm::DatabaseConnection;
+use crate::database::export::{Export, ExportFormat};
+use crate::database::import::maloja;
+use crate::database::repository::{create_scrobbles_counted, scrobbles_with_models};
+use crate::entity::album::AlbumWrite;
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::ScrobbleWrite;
+use crate::timeranges::TimeRange;
+use super::memory_database;
+use super::scrobbles::{scrobble, track};
+
+fn artist(name: &str) -> ArtistWrite {
+    ArtistWrite { id: None, name: Some(name.to_string()), mbid: None, spotify_id: None }
+}
+
+/// Scrobbles with everything the export format can hold
+fn sample_scrobbles() -> Vec<ScrobbleWrite> {
+    let mut collaboration = track("Ice Cream", "Blackpink");
+    collaboration.primary_artists.as_mut().unwrap().push(artist("Selena Gomez"));
+    collaboration.track_length = Some(176);
+    collaboration.album = Some(AlbumWrite {
+        id: None,
+        album_title: Some("The Album".to_string()),
+        album_artists: Some(vec![artist("Blackpink")]),
+        mbid: None,
+        spotify_id: None,
+    });
+    vec![
+        ScrobbleWrite { origin: Some("client:navidrome".to_string()), listen_duration: Some(174), ..scrobble(1633269900, collaboration.clone()) },
+        scrobble(1633269900, track("Fancy", "Twice")),
+        scrobble(1633270300, collaboration),
+    ]
+}
+
+/// Artists of a track have no order in the database
+fn sorted(artists: &Value) -> Value {
+    let mut artists = artists.as_array().cloned().unwrap_or_default();
+    artists.sort_by_key(|artist| artist.to_string());
+    Value::Array(artists)
+}
+
+/// Returns the amount of exported scrobbles and the file content
+async fn run_export(format: ExportFormat, timerange: TimeRange, artist_id: Option<u32>, db: &DatabaseConnection) -> (u32, Vec<u8>) {
+    let mut content = vec![];
+    let count = Export::new(format, timerange, artist_id, None, None).unwrap().write_to(&mut content, db).await.unwrap();
+    (count, content)
+}
+
+fn exported_scrobbles(export: &[u8]) -> Vec<Value> {
+    let export: Value = serde_json::from_slice(export).unwrap();
+    export["scrobbles"].as_array().unwrap().iter().map(|scrobble| {
+        let mut scrobble = scrobble.clone();
+        scrobble["track"]["artists"] = sorted(&scrobble["track"]["artists"]);
+        scrobble
+    }).collect()
+}
+
+/// Exporting, importing the export into an empty database and exporting again gives the same scrobbles
+#[tokio::test]
+async fn maloja_export_round_trip() {
+    super::environment();
+    let db = memory_database().await;
+    create_scrobbles_counted(sample_scrobbles(), false, &db).await.unwrap();
+    let (count, export) = run_export(ExportFormat::Maloja, everything(), None, &db).await;
+    assert_eq!(count, 3);
+
+    let folder = std::env::temp_dir().join(format!("maloja_test_{}_round_trip", std::process::id()));
+    fs::create_dir_all(&folder).unwrap();
+    let file = folder.join("maloja_export.json");
+    fs::write(&file, &export).unwrap();
+    let mut records = vec![];
+    maloja::read_file(&file, &mut |record| records.push(record.unwrap())).unwrap();
+    fs::remove_dir_all(&folder).unwrap();
+
+    let imported = memory_database().await;
+    let (_, inserted) = create_scrobbles_counted(records, false, &imported).await.unwrap();
+    assert_eq!(inserted, 3);
+    let (_, second_export) = run_export(ExportFormat::Maloja, everything(), None, &imported).await;
+
+    let scrobbles = exported_scrobbles(&export);
+    assert_eq!(scrobbles, exported_scrobbles(&second_export));
+    assert_eq!(scrobbles[0]["track"]["artists"], serde_json::json!(["Blackpink", "Selena Gomez"]));
+    assert_eq!(scrobbles[0]["track"]["album"]["artists"], serde_json::json!(["Blackpink"]));
+    assert_eq!(scrobbles[0]["track"]["length"], 176);
+    assert_eq!(scrobbles[0]["duration"], 174);
+    assert_eq!(scrobbles[0]["origin"], "client:navidrome");
+}
+
+fn everything() -> TimeRange {
+    TimeRange::Exact { start: Some(0), end: Some(2000000000) }
+}
+
+#[tokio::test]
+async fn csv_export() {
+    super::environment();
+    let db = memory_database().await;
+    create_scrobbles_counted(sample_scrobbles(), false, &db).await.unwrap();
+    let (count, export) = run_export(ExportFormat::Csv, everything(), None, &db).await;
+    assert_eq!(count, 3);
+
+    let export = String::from_utf8(export).unwrap();
+    let lines: Vec<&str> = export.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0], "timestamp,time_local,artists,title,album,album_artists,duration,origin");
+    assert!(lines[1].starts_with("1633269900,"));
+    assert!(lines[1].contains("Ice Cream,The Album,Blackpink,174,client:navidrome"));
+    assert!(lines[1].contains(",Blackpink; Selena Gomez,") || lines[1].contains(",Selena Gomez; Blackpink,"));
+}
+
+#[tokio::test]
+async fn listenbrainz_export() {
+    super::environment();
+    let db = memory_database().await;
+    create_scrobbles_counted(sample_scrobbles(), false, &db).await.unwrap();
+    let (count, export) = run_export(ExportFormat::Listenbrainz, everything(), None, &db).await;
+    assert_eq!(count, 3);
+
+    let listens: Vec<Value> = export.split(|byte| *byte == b'\n').filter(|line| !line.is_empty())
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert_eq!(listens.len(), 3);
+    assert_eq!(listens[0]["listened_at"], 1633269900);
+    let metadata = &listens[0]["track_metadata"];
+    assert_eq!(metadata["release_name"], "The Album");
+    assert_eq!(sorted(&metadata["additional_info"]["artist_names"]), serde_json::json!(["Blackpink", "Selena Gomez"]));
+    assert_eq!(metadata["additional_info"]["submission_client"], "client:navidrome");
+    // what ListenBrainz accepts, can be imported again
+    let reimported = parse_listen(&listens[0]).unwrap();
+    assert_eq!(reimported.timestamp, 1633269900);
+    assert_eq!(listens[1]["track_metadata"].get("release_name"), None);
+}
+
+/// Only one of artist, album and track can be used to narrow down an export
+#[tokio::test]
+async fn export_rejects_several_limits() {
+    super::environment();
+    let result = Export::new(ExportFormat::Csv, everything(), Some(1), Some(1), None);
+    assert!(matches!(result, Err(MalojaError::ParseError { .. })));
+}
+
+#[tokio::test]
+async fn export_of_time_range_and_artist() {
+    super::environment();
+    let db = memory_database().await;
+    create_scrobbles_counted(sample_scrobbles(), false, &db).await.unwrap();
+    let twice = scrobbles_with_models(everything(), None, None, None, false, &db).await.unwrap().into_iter()
+        .find(|(_, scrobble)| scrobble.track.title == "Fancy").unwrap()
+        .1.track.artists[0].id;
+
+    let (count, export) = run_export(ExportFormat::Maloja, everything(), Some(twice), &db).await;
+    assert_eq!(count, 1);
+    assert_eq!(exported_scrobbles(&export)[0]["track"]["title"], "Fancy");
+
+    let (count, export) = run_export(ExportFormat::Listenbrainz, TimeRange::Exact { start: Some(1633270000), end: None }, None, &db).await;
+    assert_eq!(count, 1);
+    let listen: Value = serde_json::from_slice(&export).unwrap();
+    assert_eq!(listen["listened_at"], 1633270300);
+}
+
+/// Exports are read in batches, but the file doesn't show where one ended
+#[tokio::test]
+async fn csv_export_over_several_batches() {
+    super::environment();
+    let db = memory_database().await;
+    let scrobbles = (0..2500).map(|index| scrobble(1633269900 + index * 300, track("Fancy", "Twice"))).collect();
+    create_scrobbles_counted(scrobbles, false, &db).await.unwrap();
+
+    let (count, export) = run_export(ExportFormat::Csv, everything(), None, &db).await;
+    assert_eq!(count, 2500);
+    let export = String::from_utf8(export).unwrap();
+    let lines: Vec<&str> = export.lines().collect();
+    assert_eq!(lines.len(), 2501);
+    assert_eq!(lines.iter().filter(|line| line.starts_with("timestamp,")).count(), 1);
+    assert!(lines[2500].starts_with(&format!("{},", 1633269900 + 2499 * 300)));
+}
@@ -0,0 +1,214 @@
+use std::fs;
+use std::path::Path;
+use crate::database::errors::MalojaError;
+use crate::database::import::{import_file, lastfm, listenbrainz, maloja_legacy, scrobbler_log, spotify, Parser, RecordSink};
+use crate::database::views::ImportState;
+use crate::entity::scrobble::ScrobbleWrite;
+use crate::entity::track::TrackWrite;
+use super::scrobbles::{scrobble, track};
+
+/// Writes the sample to a file of that name and returns everything the parser hands on
+fn parse(parser: Parser, file_name: &str, content: &str) -> Vec<Result<ScrobbleWrite, MalojaError>> {
+    super::environment();
+    let folder = std::env::temp_dir().join(format!("maloja_test_{}_{}", std::process::id(), file_name));
+    fs::create_dir_all(&folder).unwrap();
+    let file = folder.join(file_name);
+    fs::write(&file, content).unwrap();
+    let mut records = vec![];
+    parser(&file, &mut |record| records.push(record)).unwrap();
+    fs::remove_dir_all(&folder).unwrap();
+    records
+}
+
+fn rejection(record: &Result<ScrobbleWrite, MalojaError>) -> String {
+    match record {
+        Err(MalojaError::ParseError { message }) => message.clone(),
+        other => panic!("Expected a rejection, got {:?}", other),
+    }
+}
+
+fn artist_names(scrobble: &ScrobbleWrite) -> Vec<String> {
+    scrobble.track.primary_artists.iter().flatten().filter_map(|artist| artist.name.clone()).collect()
+}
+
+#[test]
+fn lastfm_csv_without_header() {
+    let records = parse(lastfm::read_csv, "lastfm.csv", "\
+Blackpink,Square One,Whistle,\"03 Oct 2021, 14:05\"
+Blackpink,,Stay,03 Oct 2021 14:09
+Twice,Fancy You,,03 Oct 2021 14:13
+");
+    assert_eq!(records.len(), 3);
+    let first = records[0].as_ref().unwrap();
+    // dates are UTC
+    assert_eq!(first.timestamp, 1633269900);
+    assert_eq!(artist_names(first), vec!["Blackpink"]);
+    assert_eq!(first.track.title.as_deref(), Some("Whistle"));
+    assert_eq!(first.track.album.as_ref().and_then(|album| album.album_title.as_deref()), Some("Square One"));
+    assert!(records[1].as_ref().unwrap().track.album.is_none());
+    assert_eq!(rejection(&records[2]), "Row has no track");
+}
+
+#[test]
+fn lastfm_csv_with_header() {
+    let records = parse(lastfm::read_csv, "lastfm_header.csv", "\
+uts,utc_time,artist,artist_mbid,album,album_mbid,track,track_mbid
+1633269900,\"03 Oct 2021, 14:05\",Blackpink,,Square One,,Whistle,1d48f0c7-f65f-4e3d-8b3e-b066531b9a67
+");
+    assert_eq!(records.len(), 1);
+    let scrobble = records[0].as_ref().unwrap();
+    assert_eq!(scrobble.timestamp, 1633269900);
+    assert_eq!(scrobble.track.mbid.as_deref(), Some("1d48f0c7-f65f-4e3d-8b3e-b066531b9a67"));
+}
+
+/// The track that is playing right now is in the first page, but hasn't been scrobbled yet
+#[test]
+fn lastfm_json_skips_now_playing() {
+    let records = parse(lastfm::read_json, "lastfm.json", r##"[{"recenttracks": {"track": [
+        {"name": "Stay", "artist": {"#text": "Blackpink"}, "album": {"#text": ""}, "@attr": {"nowplaying": "true"}},
+        {"name": "Whistle", "mbid": "", "artist": {"#text": "Blackpink", "mbid": ""}, "album": {"#text": "Square One"}, "date": {"uts": "1633269900"}},
+        {"name": "Fancy", "artist": {"#text": "Twice"}, "album": {"#text": "Fancy You"}}
+    ]}}]"##);
+    assert_eq!(records.len(), 2);
+    let scrobble = records[0].as_ref().unwrap();
+    assert_eq!(scrobble.timestamp, 1633269900);
+    assert_eq!(scrobble.track.title.as_deref(), Some("Whistle"));
+    assert!(scrobble.track.mbid.is_none());
+    assert_eq!(rejection(&records[1]), "Track has no date");
+}
+
+#[test]
+fn spotify_extended_history() {
+    let records = parse(spotify::read_extended_history, "Streaming_History_Audio_2021.json", r#"[
+        {"ts": "2021-10-03T14:08:20Z", "ms_played": 200000, "master_metadata_track_name": "Whistle",
+         "master_metadata_album_artist_name": "Blackpink", "master_metadata_album_album_name": "Square One",
+         "spotify_track_uri": "spotify:track:6NEoeBLQbOMw92qMeLfI40", "episode_name": null},
+        {"ts": "2021-10-03T14:09:00Z", "ms_played": 12000, "master_metadata_track_name": "Stay",
+         "master_metadata_album_artist_name": "Blackpink", "master_metadata_album_album_name": "Square One",
+         "spotify_track_uri": "spotify:track:1HVMnGeIsmTBCNMn1c7DQY", "episode_name": null},
+        {"ts": "2021-10-03T15:00:00Z", "ms_played": 1800000, "master_metadata_track_name": null,
+         "master_metadata_album_artist_name": null, "master_metadata_album_album_name": null,
+         "spotify_track_uri": null, "episode_name": "Episode 12"}
+    ]"#);
+    assert_eq!(records.len(), 3);
+    let scrobble = records[0].as_ref().unwrap();
+    // the time is when playback ended
+    assert_eq!(scrobble.timestamp, 1633269900);
+    assert_eq!(scrobble.listen_duration, Some(200));
+    assert_eq!(scrobble.track.spotify_id.as_deref(), Some("6NEoeBLQbOMw92qMeLfI40"));
+    assert_eq!(artist_names(scrobble), vec!["Blackpink"]);
+    assert_eq!(rejection(&records[1]), "Played for less than 30 seconds");
+    assert_eq!(rejection(&records[2]), "Not a music track");
+}
+
+#[test]
+fn spotify_basic_history() {
+    let records = parse(spotify::read_basic_history, "StreamingHistory0.json", r#"[
+        {"endTime": "2021-10-03 14:08", "artistName": "Blackpink", "trackName": "Whistle", "msPlayed": 60000},
+        {"endTime": "2021-10-03 14:09", "artistName": "Blackpink", "trackName": "Stay", "msPlayed": 29999}
+    ]"#);
+    assert_eq!(records.len(), 2);
+    let scrobble = records[0].as_ref().unwrap();
+    assert_eq!(scrobble.timestamp, 1633270020);
+    assert!(scrobble.track.album.is_none());
+    assert_eq!(rejection(&records[1]), "Played for less than 30 seconds");
+}
+
+#[test]
+fn listenbrainz_json_lines() {
+    let records = parse(listenbrainz::read_file, "listenbrainz_user.jsonl", r#"
+{"listened_at": 1633269900, "track_metadata": {"artist_name": "Blackpink, Selena Gomez", "track_name": "Ice Cream", "release_name": "The Album", "additional_info": {"artist_names": ["Blackpink", "Selena Gomez"], "release_artist_name": "Blackpink"}}}
+{"track_metadata": {"artist_name": "Blackpink", "track_name": "Stay"}}
+{"listened_at": 1633270100, "track_metadata":
+"#);
+    assert_eq!(records.len(), 3);
+    let scrobble = records[0].as_ref().unwrap();
+    assert_eq!(scrobble.timestamp, 1633269900);
+    assert_eq!(artist_names(scrobble), vec!["Blackpink", "Selena Gomez"]);
+    let album = scrobble.track.album.as_ref().unwrap();
+    assert_eq!(album.album_title.as_deref(), Some("The Album"));
+    assert_eq!(album.album_artists.iter().flatten().filter_map(|artist| artist.name.as_deref()).collect::<Vec<_>>(), vec!["Blackpink"]);
+    assert_eq!(rejection(&records[1]), "Listen is missing listened_at");
+    assert!(records[2].is_err());
+}
+
+/// Without a timezone, the device's clock is taken to be in the configured one
+#[test]
+fn scrobbler_log_with_unknown_timezone() {
+    let records = parse(scrobbler_log::read_file, "unknown.scrobbler.log", "\
+#AUDIOSCROBBLER/1.1
+#TZ/UNKNOWN
+#CLIENT/Rockbox sansa $Revision$
+Blackpink\tSquare One\tWhistle\t1\t211\tL\t1633269900\t1d48f0c7-f65f-4e3d-8b3e-b066531b9a67
+Blackpink\tSquare One\tStay\t2\t230\tS\t1633270111\t
+Blackpink\tSquare One\t\t3\t180\tL\t1633270341\t
+");
+    assert_eq!(records.len(), 3);
+    let scrobble = records[0].as_ref().unwrap();
+    // 14:05 in Vienna, two hours ahead of UTC in October
+    assert_eq!(scrobble.timestamp, 1633269900 - 2 * 3600);
+    assert_eq!(scrobble.track.track_length, Some(211));
+    assert_eq!(scrobble.track.mbid.as_deref(), Some("1d48f0c7-f65f-4e3d-8b3e-b066531b9a67"));
+    assert_eq!(scrobble.origin.as_deref(), Some("import:scrobbler_log:Rockbox sansa $Revision$"));
+    assert_eq!(rejection(&records[1]), "Skipped on the device");
+    assert_eq!(rejection(&records[2]), "Line has no title");
+}
+
+#[test]
+fn scrobbler_log_in_utc() {
+    let records = parse(scrobbler_log::read_file, "utc.scrobbler.log", "\
+#AUDIOSCROBBLER/1.1
+#TZ/UTC
+Blackpink\t\tWhistle\t\t211\tL\t1633269900\t
+");
+    assert_eq!(records.len(), 1);
+    let scrobble = records[0].as_ref().unwrap();
+    assert_eq!(scrobble.timestamp, 1633269900);
+    assert!(scrobble.track.album.is_none());
+}
+
+#[test]
+fn legacy_maloja_tsv() {
+    let records = parse(maloja_legacy::read_path, "2021_10.tsv", "\
+1633269900\tBlackpink␟Selena Gomez\tIce Cream\tThe Album\t180
+1633270100\tBlackpink\tStay\t\t
+1633270341\t␟\tWhistle\t\t
+1633270600\tBlackpink\t\t\t
+");
+    assert_eq!(records.len(), 4);
+    let scrobble = records[0].as_ref().unwrap();
+    assert_eq!(scrobble.timestamp, 1633269900);
+    assert_eq!(artist_names(scrobble), vec!["Blackpink", "Selena Gomez"]);
+    assert_eq!(scrobble.listen_duration, Some(180));
+    // without album artists, the album belongs to the track artists
+    let album = scrobble.track.album.as_ref().unwrap();
+    assert_eq!(album.album_artists.iter().flatten().filter_map(|artist| artist.name.as_deref()).collect::<Vec<_>>(), vec!["Blackpink", "Selena Gomez"]);
+    let scrobble = records[1].as_ref().unwrap();
+    assert!(scrobble.track.album.is_none());
+    assert!(scrobble.listen_duration.is_none());
+    // only the separator, but no names
+    assert_eq!(rejection(&records[2]), "Line has no artists");
+    assert_eq!(rejection(&records[3]), "Line has no title");
+}
+
+/// Valid scrobbles around one that only fails once it's written, because its track doesn't exist
+fn records_with_unknown_track(_: &Path, sink: &mut RecordSink) -> Result<(), MalojaError> {
+    sink(Ok(scrobble(1633269900, track("Whistle", "Blackpink"))));
+    sink(Ok(scrobble(1633270100, TrackWrite { id: Some(9999), ..track("Stay", "Blackpink") })));
+    sink(Ok(scrobble(1633270300, track("Fancy", "Twice"))));
+    sink(Ok(scrobble(1633270300, track("Fancy", "Twice"))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn failing_batch_does_not_fail_import() {
+    super::environment();
+    let db = super::memory_database().await;
+    let report = import_file(Path::new("records.json"), "test records", records_with_unknown_track, &db).await;
+    assert!(matches!(report.state, ImportState::Finished));
+    assert_eq!(report.parsed, 4);
+    assert_eq!(report.created, 2);
+    assert_eq!(report.duplicate, 1);
+    assert_eq!(report.rejected, 1);
+    assert_eq!(report.rejection_reasons.values().sum::<u32>(), 1);
+}
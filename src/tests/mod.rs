@@ -1,20 +1,49 @@
-mod fixtures;
+mod timeranges;
+mod scrobbles;
+mod imports;
+mod exports;
+mod audioscrobbler;
 
 use super::*;
 use std::fs;
 
+/// Not UTC, so that tests notice when local time and UTC are mixed up
+const TEST_CONFIG: &str = "timezone = \"Europe/Vienna\"\n";
+
+/// Keeps tests away from the folders and configuration of an actual installation
+fn environment() {
+    static ENVIRONMENT: std::sync::Once = std::sync::Once::new();
+    ENVIRONMENT.call_once(|| {
+        std::env::set_var("MALOJA_DATA_PATH", "./testing/data");
+        std::env::set_var("MALOJA_CONFIG_PATH", "./testing/config");
+        std::env::set_var("MALOJA_LOG_PATH", "./testing/log");
+        fs::create_dir_all("./testing/config").unwrap();
+        fs::write("./testing/config/maloja.toml", TEST_CONFIG).unwrap();
+    });
+}
+
+/// Empty database that only exists for one test
+async fn memory_database() -> sea_orm::DatabaseConnection {
+    use std::str::FromStr;
+    use sea_orm::sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+    let options = SqliteConnectOptions::from_str("sqlite::memory:").unwrap().foreign_keys(true);
+    // every connection to memory would have its own database
+    let pool = SqlitePoolOptions::new().max_connections(1).connect_with(options).await.unwrap();
+    let db = sea_orm::DatabaseConnection::from(pool);
+    database::migrations::migrate(&db).await.unwrap();
+    db
+}
+
 async fn common() {
-    std::env::set_var("MALOJA_DATA_PATH", "./testing/data");
-    std::env::set_var("MALOJA_CONFIG_PATH", "./testing/config");
-    std::env::set_var("MALOJA_LOG_PATH", "./testing/log");
+    environment();
 
     fs::remove_dir_all("./testing/data");
     fs::remove_dir_all("./testing/config");
     fs::remove_dir_all("./testing/log");
 
-    database::init_db().await.unwrap();
+    let db = database::init_db().await.unwrap();
 
-    fixtures::fixture().await;
+    database::fixtures::fixture(&db).await;
 }
 
 
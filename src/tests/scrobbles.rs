@@ -0,0 +1,86 @@
+use sea_orm::{EntityTrait, PaginatorTrait};
+use crate::database::errors::MalojaError;
+use crate::database::repository::create_scrobbles_counted;
+use crate::entity::{artist, scrobble, track};
+use crate::entity::artist::ArtistWrite;
+use crate::entity::scrobble::ScrobbleWrite;
+use crate::entity::track::TrackWrite;
+use super::memory_database;
+
+pub fn track(title: &str, artist: &str) -> TrackWrite {
+    TrackWrite {
+        id: None,
+        title: Some(title.to_string()),
+        primary_artists: Some(vec![ArtistWrite { id: None, name: Some(artist.to_string()), mbid: None, spotify_id: None }]),
+        secondary_artists: None,
+        track_length: None,
+        album: None,
+        mbid: None,
+        spotify_id: None,
+    }
+}
+
+pub fn scrobble(timestamp: i64, track: TrackWrite) -> ScrobbleWrite {
+    ScrobbleWrite { timestamp, track, origin: None, listen_duration: None, raw_scrobble: None }
+}
+
+#[tokio::test]
+async fn same_track_in_same_second_is_one_scrobble() {
+    let db = memory_database().await;
+    let write = scrobble(1700000000, track("Whistle", "Blackpink"));
+    let (_, inserted) = create_scrobbles_counted(vec![write.clone(), write.clone()], false, &db).await.unwrap();
+    assert_eq!(inserted, 1);
+    // submitting it again later is a duplicate as well
+    let (created, inserted) = create_scrobbles_counted(vec![write.clone()], false, &db).await.unwrap();
+    assert_eq!(inserted, 0);
+    assert_eq!(created[&write].timestamp, 1700000000);
+    assert_eq!(scrobble::Entity::find().count(&db).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn different_tracks_in_same_second_are_separate_scrobbles() {
+    let db = memory_database().await;
+    let first = scrobble(1700000000, track("Whistle", "Blackpink"));
+    let second = scrobble(1700000000, track("Stay", "Blackpink"));
+    let (created, inserted) = create_scrobbles_counted(vec![first.clone(), second.clone()], false, &db).await.unwrap();
+    assert_eq!(inserted, 2);
+    assert_ne!(created[&first].track_id, created[&second].track_id);
+}
+
+/// A rejected submission doesn't create anything, not even the artists and tracks of its new scrobbles
+#[tokio::test]
+async fn rejected_submission_leaves_nothing_behind() {
+    let db = memory_database().await;
+    let existing = scrobble(1700000000, track("Whistle", "Blackpink"));
+    create_scrobbles_counted(vec![existing.clone()], false, &db).await.unwrap();
+
+    let new = scrobble(1700000300, track("Fancy", "Twice"));
+    let result = create_scrobbles_counted(vec![new.clone(), existing.clone()], true, &db).await;
+    assert!(matches!(result, Err(MalojaError::ScrobbleExists { timestamp: 1700000000 })));
+    // the same new scrobble twice in one submission
+    let result = create_scrobbles_counted(vec![new.clone(), new.clone()], true, &db).await;
+    assert!(matches!(result, Err(MalojaError::ScrobbleExists { timestamp: 1700000300 })));
+
+    assert_eq!(scrobble::Entity::find().count(&db).await.unwrap(), 1);
+    assert_eq!(track::Entity::find().count(&db).await.unwrap(), 1);
+    assert_eq!(artist::Entity::find().count(&db).await.unwrap(), 1);
+
+    let (_, inserted) = create_scrobbles_counted(vec![new], true, &db).await.unwrap();
+    assert_eq!(inserted, 1);
+}
+
+/// Tracks from Spotify keep their ID when their title changes, whether that's in the same import batch or a later one
+#[tokio::test]
+async fn tracks_are_found_by_spotify_id() {
+    let db = memory_database().await;
+    let with_id = |title: &str| TrackWrite { spotify_id: Some("6NEoeBLQbOMw92qMeLfI40".to_string()), ..track(title, "Blackpink") };
+    let first = scrobble(1700000000, with_id("Whistle"));
+    let renamed = scrobble(1700000300, with_id("WHISTLE (Remastered)"));
+    let (created, _) = create_scrobbles_counted(vec![first.clone(), renamed.clone()], false, &db).await.unwrap();
+    assert_eq!(created[&first].track_id, created[&renamed].track_id);
+
+    let later = scrobble(1700000600, with_id("Whistle - 2016 Version"));
+    let (created_later, _) = create_scrobbles_counted(vec![later.clone()], false, &db).await.unwrap();
+    assert_eq!(created_later[&later].track_id, created[&first].track_id);
+    assert_eq!(track::Entity::find().count(&db).await.unwrap(), 1);
+}
@@ -0,0 +1,123 @@
+use std::ops::RangeInclusive;
+use chrono::{Datelike, Days, NaiveDate, Weekday};
+use crate::uri::QueryTimerange;
+use crate::timeranges::{first_week_start, next_week, previous_week, week_of, weeks_in_year, BaseTimeRange, TimeRange};
+
+const WEEKDAYS: [Weekday; 7] = [Weekday::Sun, Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat];
+const YEARS: RangeInclusive<i32> = 1900..=2100;
+
+/// With weeks starting on Monday, the numbering has to be exactly that of ISO weeks
+#[test]
+fn monday_weeks_are_iso_weeks() {
+    let mut date = NaiveDate::from_ymd_opt(*YEARS.start(), 1, 1).unwrap();
+    while date.year() <= *YEARS.end() {
+        let iso = date.iso_week();
+        assert_eq!(week_of(date, Weekday::Mon), (iso.year(), iso.week() as u8), "{}", date);
+        date = date.succ_opt().unwrap();
+    }
+}
+
+/// Every day is in exactly one week, and stepping forward never skips or repeats one
+#[test]
+fn weeks_cover_every_day_once() {
+    for week_begin in WEEKDAYS {
+        let mut first_day = first_week_start(*YEARS.start(), week_begin);
+        let mut week = (*YEARS.start(), 1);
+        while week.0 <= *YEARS.end() {
+            assert_eq!(first_day.weekday(), week_begin);
+            for offset in 0..7 {
+                let date = first_day + Days::new(offset);
+                assert_eq!(week_of(date, week_begin), week, "{} with weeks starting {}", date, week_begin);
+            }
+            first_day = first_day + Days::new(7);
+            week = next_week(week.0, week.1, week_begin);
+        }
+    }
+}
+
+#[test]
+fn years_have_52_or_53_weeks() {
+    for week_begin in WEEKDAYS {
+        for year in YEARS {
+            let weeks = weeks_in_year(year, week_begin);
+            assert!(weeks == 52 || weeks == 53, "{} has {} weeks starting {}", year, weeks, week_begin);
+            // at least four days of the first week are in the new year
+            let start = first_week_start(year, week_begin);
+            assert!(start >= NaiveDate::from_ymd_opt(year - 1, 12, 29).unwrap() && start <= NaiveDate::from_ymd_opt(year, 1, 4).unwrap());
+        }
+    }
+    assert_eq!(weeks_in_year(2015, Weekday::Mon), 53);
+    assert_eq!(weeks_in_year(2020, Weekday::Mon), 53);
+    assert_eq!(weeks_in_year(2021, Weekday::Mon), 52);
+    assert_eq!(weeks_in_year(2026, Weekday::Mon), 53);
+}
+
+#[test]
+fn stepping_back_and_forth() {
+    for week_begin in WEEKDAYS {
+        for year in YEARS {
+            for week in 1..=weeks_in_year(year, week_begin) {
+                let (next_year, next) = next_week(year, week, week_begin);
+                assert_eq!(previous_week(next_year, next, week_begin), (year, week));
+                let (previous_year, previous) = previous_week(year, week, week_begin);
+                assert_eq!(next_week(previous_year, previous, week_begin), (year, week));
+            }
+        }
+    }
+}
+
+fn month(year: i32, month: u8) -> Option<BaseTimeRange> {
+    Some(BaseTimeRange::Month { year, month })
+}
+
+fn day(year: i32, month: u8, day: u8) -> Option<BaseTimeRange> {
+    Some(BaseTimeRange::Day { year, month, day })
+}
+
+/// Composite ranges step by their own length
+#[test]
+fn composite_neighbours() {
+    super::environment();
+    let quarter = TimeRange::Composite { start: month(2021, 10), end: month(2021, 12) };
+    assert_eq!(quarter.previous().unwrap().to_query(), "from=2021/7&to=2021/9");
+    assert_eq!(quarter.next().unwrap().to_query(), "from=2022/1&to=2022/3");
+
+    let days = TimeRange::Composite { start: day(2021, 2, 27), end: day(2021, 3, 2) };
+    assert_eq!(days.previous().unwrap().to_query(), "from=2021/2/23&to=2021/2/26");
+    assert_eq!(days.next().unwrap().to_query(), "from=2021/3/3&to=2021/3/6");
+
+    // a year and a month have months in common
+    let mixed = TimeRange::Composite { start: Some(BaseTimeRange::Year { year: 2021 }), end: month(2021, 3) };
+    assert_eq!(mixed.previous().unwrap().to_query(), "from=2020/10&to=2020/12");
+}
+
+/// Stepping doesn't depend on the length of the range, and ends where the calendar does
+#[test]
+fn composite_neighbours_of_long_ranges() {
+    super::environment();
+    let long = TimeRange::Composite { start: Some(BaseTimeRange::Year { year: 100 }), end: day(9999, 12, 31) };
+    assert!(long.previous().unwrap().to_query().starts_with("from=-"));
+    assert!(long.next().is_some());
+
+    let huge = TimeRange::Composite { start: Some(BaseTimeRange::Year { year: -200_000 }), end: Some(BaseTimeRange::Year { year: 200_000 }) };
+    assert!(huge.previous().is_none());
+    assert!(huge.next().is_none());
+}
+
+/// Neighbours of exact ranges can be requested again with their query
+#[test]
+fn exact_neighbours() {
+    super::environment();
+    let range = TimeRange::Exact { start: Some(-500), end: Some(4999) };
+    let previous = range.previous().unwrap();
+    assert_eq!(previous.timestamp_boundaries(), (-6000, -501));
+    let query = previous.to_query();
+    let parameters: Vec<&str> = query.split('&').map(|parameter| parameter.split_once('=').unwrap().1).collect();
+    let parsed = QueryTimerange::match_bounds(Some(parameters[0]), Some(parameters[1])).unwrap();
+    assert_eq!(parsed.timestamp_boundaries(), (-6000, -501));
+
+    // neither of these can be stepped without leaving the calendar
+    assert!(TimeRange::Exact { start: Some(0), end: Some(i64::MAX) }.previous().is_none());
+    assert!(TimeRange::Exact { start: Some(0), end: Some(i64::MAX) }.next().is_none());
+    assert!(QueryTimerange::match_bounds(Some("00000"), Some("9223372036854775807")).is_err());
+}
@@ -1,21 +1,84 @@
 use std::fmt::{Display, Formatter};
-use chrono::{naive::Days, DateTime, Datelike, TimeZone, Weekday, NaiveDate, NaiveDateTime, Months, Utc};
+use chrono::{naive::Days, DateTime, Datelike, TimeZone, Weekday, NaiveDate, Months, SecondsFormat, TimeDelta, Utc};
 use chrono_tz::Tz;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use strum_macros::Display;
 use utoipa::{PartialSchema, ToSchema};
 use utoipa::openapi::{RefOr, Schema};
+use crate::configuration::{CONFIG, TIMEZONE, WEEK_BEGIN};
+use crate::database;
 
-const TIMEZONE: Tz = Tz::Europe__Vienna; //AEIOU
-const WEEK_BEGIN: Weekday = Weekday::Sun;
-const FIRST_STAMP: i64 = 825092900; //TODO
 
 pub const ALL_TIME: TimeRange = TimeRange::Infinite {};
 
+/// Midnight of that day in the configured timezone, or the end of the transition if the clocks are changed at midnight
+fn start_of_day(date: NaiveDate) -> DateTime<Tz> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    TIMEZONE.from_local_datetime(&midnight).earliest()
+        .or_else(|| TIMEZONE.from_local_datetime(&(midnight + TimeDelta::hours(1))).earliest())
+        .unwrap()
+}
+
+/// The last second before the next day starts
+fn end_of_day(date: NaiveDate) -> DateTime<Tz> {
+    start_of_day(date.succ_opt().unwrap()) - TimeDelta::seconds(1)
+}
+
+
+
+/// Start of the first week of the year. Weeks are numbered like ISO weeks, but begin on the configured day:
+/// the first week is the one with at least four of its days in the new year
+pub fn first_week_start(year: i32, week_begin: Weekday) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, 1, 4).unwrap().week(week_begin).first_day()
+}
+
+/// Either 52 or 53
+pub fn weeks_in_year(year: i32, week_begin: Weekday) -> u8 {
+    ((first_week_start(year + 1, week_begin) - first_week_start(year, week_begin)).num_days() / 7) as u8
+}
+
+/// Year and number of the week the day belongs to. Days around New Year can be in a week of the other year
+pub fn week_of(date: NaiveDate, week_begin: Weekday) -> (i32, u8) {
+    let year = if date < first_week_start(date.year(), week_begin) {
+        date.year() - 1
+    } else if date >= first_week_start(date.year() + 1, week_begin) {
+        date.year() + 1
+    } else {
+        date.year()
+    };
+    (year, ((date - first_week_start(year, week_begin)).num_days() / 7 + 1) as u8)
+}
+
+pub fn previous_week(year: i32, week: u8, week_begin: Weekday) -> (i32, u8) {
+    match week {
+        1 => (year - 1, weeks_in_year(year - 1, week_begin)),
+        week => (year, week - 1),
+    }
+}
+
+pub fn next_week(year: i32, week: u8, week_begin: Weekday) -> (i32, u8) {
+    match week >= weeks_in_year(year, week_begin) {
+        true => (year + 1, 1),
+        false => (year, week + 1),
+    }
+}
 
+/// Ends of a composite range as the same type, so it can be stepped through. Mixed types use the smaller one,
+/// except that weeks and months don't line up, so these use days
+fn common_units(start: &BaseTimeRange, end: &BaseTimeRange) -> (BaseTimeRange, BaseTimeRange) {
+    let unit = match (start.range_type(), end.range_type()) {
+        (a, b) if a == b => a,
+        (RangeType::Day, _) | (_, RangeType::Day) | (RangeType::Week, _) | (_, RangeType::Week) => RangeType::Day,
+        _ => RangeType::Month,
+    };
+    (
+        BaseTimeRange::containing(start.datetime_boundaries().0, unit.clone()),
+        BaseTimeRange::containing(end.datetime_boundaries().1, unit),
+    )
+}
 
 /// The basic time range types that correspond to gregorian units
-#[derive(Clone, Debug, Serialize, ToSchema)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
 pub enum BaseTimeRange {
     Day { year: i32, month: u8, day: u8 },
     Week { year: i32, week: u8 },
@@ -23,7 +86,7 @@ pub enum BaseTimeRange {
     Year { year: i32 },
 }
 /// A fieldless enum simply to select the different types of time ranges as used by [`BaseTimeRange`]
-#[derive(Clone, Debug, Display)]
+#[derive(Clone, Debug, PartialEq, Eq, Display)]
 pub enum RangeType {
     Day, Week, Month, Year
 }
@@ -34,62 +97,122 @@ pub enum TimeRange {
     Simple(BaseTimeRange),
     Composite { start: Option<BaseTimeRange>, end: Option<BaseTimeRange> },
     Infinite, //represented by Composite { None, None } as well, remove?
+    /// The last few days, weeks, months or years until now. Unlike the others, this moves with the current time
+    Rolling { amount: u32, unit: RangeType },
+    /// Exact timestamps as start and end, for ranges that don't line up with calendar units
+    Exact { start: Option<i64>, end: Option<i64> },
 }
 
 
 
 
 impl BaseTimeRange {
+    /// The range of that type that includes the moment
+    pub fn containing(time: DateTime<Tz>, range_type: RangeType) -> Self {
+        Self::containing_date(time.date_naive(), range_type)
+    }
+
+    fn containing_date(date: NaiveDate, range_type: RangeType) -> Self {
+        match range_type {
+            RangeType::Day => BaseTimeRange::Day { year: date.year(), month: date.month() as u8, day: date.day() as u8 },
+            RangeType::Week => {
+                let (year, week) = week_of(date, *WEEK_BEGIN);
+                BaseTimeRange::Week { year, week }
+            }
+            RangeType::Month => BaseTimeRange::Month { year: date.year(), month: date.month() as u8 },
+            RangeType::Year => BaseTimeRange::Year { year: date.year() },
+        }
+    }
+
+    pub fn range_type(&self) -> RangeType {
+        match self {
+            BaseTimeRange::Day { .. } => RangeType::Day,
+            BaseTimeRange::Week { .. } => RangeType::Week,
+            BaseTimeRange::Month { .. } => RangeType::Month,
+            BaseTimeRange::Year { .. } => RangeType::Year,
+        }
+    }
+
+    fn first_day(&self) -> NaiveDate {
+        match self {
+            BaseTimeRange::Day { year, month, day } => NaiveDate::from_ymd_opt(*year, *month as u32, *day as u32).unwrap(),
+            BaseTimeRange::Week { year, week } => first_week_start(*year, *WEEK_BEGIN) + Days::new(7 * (*week as u64 - 1)),
+            BaseTimeRange::Month { year, month } => NaiveDate::from_ymd_opt(*year, *month as u32, 1).unwrap(),
+            BaseTimeRange::Year { year } => NaiveDate::from_ymd_opt(*year, 1, 1).unwrap(),
+        }
+    }
+
+    /// How many ranges of the same type come after this one until the other. Only for ranges of the same type
+    fn distance(&self, other: &Self) -> i64 {
+        let distance = match (self, other) {
+            (BaseTimeRange::Day { .. }, BaseTimeRange::Day { .. }) => (other.first_day() - self.first_day()).num_days(),
+            (BaseTimeRange::Week { .. }, BaseTimeRange::Week { .. }) => (other.first_day() - self.first_day()).num_weeks(),
+            (BaseTimeRange::Month { year: first_year, month: first_month }, BaseTimeRange::Month { year, month }) => {
+                12 * (*year as i64 - *first_year as i64) + (*month as i64 - *first_month as i64)
+            }
+            (BaseTimeRange::Year { year: first_year }, BaseTimeRange::Year { year }) => *year as i64 - *first_year as i64,
+            _ => 0,
+        };
+        distance.max(0)
+    }
+
+    /// The range of the same type that many steps later, or earlier for negative steps.
+    /// Nothing if that's too close to the limits of the calendar
+    fn shift(&self, steps: i64) -> Option<Self> {
+        let first_day = match self {
+            BaseTimeRange::Day { .. } => self.first_day().checked_add_signed(TimeDelta::try_days(steps)?)?,
+            BaseTimeRange::Week { .. } => self.first_day().checked_add_signed(TimeDelta::try_weeks(steps)?)?,
+            BaseTimeRange::Month { year, month } => {
+                let months = (*year as i64 * 12 + *month as i64 - 1).checked_add(steps)?;
+                NaiveDate::from_ymd_opt(i32::try_from(months.div_euclid(12)).ok()?, months.rem_euclid(12) as u32 + 1, 1)?
+            }
+            BaseTimeRange::Year { year } => NaiveDate::from_ymd_opt(i32::try_from((*year as i64).checked_add(steps)?).ok()?, 1, 1)?,
+        };
+        // the boundaries and neighbours of the range have to exist as well
+        if first_day.year() <= NaiveDate::MIN.year() + 1 || first_day.year() >= NaiveDate::MAX.year() - 1 {
+            return None;
+        }
+        Some(Self::containing_date(first_day, self.range_type()))
+    }
+
     fn datetime_boundaries(&self) -> (DateTime<Tz>, DateTime<Tz>) {
         match self {
             BaseTimeRange::Day { year, month, day } => {
                 let thisday = NaiveDate::from_ymd_opt(*year, *month as u32, *day as u32).unwrap();
                 (
-                    thisday.and_hms_opt(0, 0, 0).unwrap().and_local_timezone(TIMEZONE).unwrap(),
-                    thisday.and_hms_opt(23, 59, 59).unwrap().and_local_timezone(TIMEZONE).unwrap(),
+                    start_of_day(thisday),
+                    end_of_day(thisday),
                 )
             }
             BaseTimeRange::Week { year, week } => {
-                let first_day_this_year = NaiveDate::from_ymd_opt(*year as i32, 1, 1).unwrap();
-                // weekoffset = days ahead of last week start
-                // weekoffset2 = days until next week start
-                let weekoffset: i32 = ((7 + first_day_this_year.weekday().num_days_from_sunday() - WEEK_BEGIN.num_days_from_sunday()) % 7) as i32;
-                let weekoffset2: i32 = 7 - weekoffset;
-                let use_offset = if weekoffset > weekoffset2 { weekoffset2 } else { -weekoffset };
-                let first_week_start = if (use_offset >= 0) {
-                    first_day_this_year.checked_add_days(Days::new(use_offset as u64)).unwrap()
-                } else {
-                    first_day_this_year.checked_sub_days(Days::new(-use_offset as u64)).unwrap()
-                };
-                let firstday = first_week_start.checked_add_days(Days::new(7u64 * *week as u64)).unwrap();
+                let firstday = first_week_start(*year, *WEEK_BEGIN) + Days::new(7 * (*week as u64 - 1));
                 let lastday = firstday.checked_add_days(Days::new(6)).unwrap();
                 (
-                    firstday.and_hms_opt(0, 0, 0).unwrap().and_local_timezone(TIMEZONE).unwrap(),
-                    lastday.and_hms_opt(23, 59, 59).unwrap().and_local_timezone(TIMEZONE).unwrap(),
+                    start_of_day(firstday),
+                    end_of_day(lastday),
                 )
-
             }
             BaseTimeRange::Month { year, month } => {
                 let firstday = NaiveDate::from_ymd_opt(*year, *month as u32, 1).unwrap();
                 let lastday = firstday.checked_add_months(Months::new(1)).unwrap().checked_sub_days(Days::new(1)).unwrap();
                 (
-                    firstday.and_hms_opt(0, 0, 0).unwrap().and_local_timezone(TIMEZONE).unwrap(),
-                    lastday.and_hms_opt(23, 59, 59).unwrap().and_local_timezone(TIMEZONE).unwrap(),
+                    start_of_day(firstday),
+                    end_of_day(lastday),
                 )
             }
             BaseTimeRange::Year { year } => {
                 let firstday = NaiveDate::from_ymd_opt(*year as i32, 1, 1).unwrap();
                 let lastday = NaiveDate::from_ymd_opt(*year as i32, 12, 31).unwrap();
                 (
-                    firstday.and_hms_opt(0, 0, 0).unwrap().and_local_timezone(TIMEZONE).unwrap(),
-                    lastday.and_hms_opt(23, 59, 59).unwrap().and_local_timezone(TIMEZONE).unwrap(),
+                    start_of_day(firstday),
+                    end_of_day(lastday),
                 )
 
             }
         }
     }
 
-    fn previous(&self) -> Self {
+    pub fn previous(&self) -> Self {
         let (first,last) = self.datetime_boundaries();
         match self {
             BaseTimeRange::Day { .. } => {
@@ -97,12 +220,7 @@ impl BaseTimeRange {
                 BaseTimeRange::Day { year: new.year(), month: new.month() as u8, day: new.day() as u8 }
             }
             BaseTimeRange::Week { year, week } => {
-                //let new = first.checked_sub_days(Days::new(7)).unwrap();
-                let (mut year, mut week) = (year.clone(), week - 1);
-                if week < 1 {
-                    year -= 1;
-                    week += 52;
-                }
+                let (year, week) = previous_week(*year, *week, *WEEK_BEGIN);
                 BaseTimeRange::Week { year, week }
             }
             BaseTimeRange::Month { .. } => {
@@ -116,7 +234,7 @@ impl BaseTimeRange {
         }
     }
 
-    fn next(&self) -> Self {
+    pub fn next(&self) -> Self {
         let (first,last) = self.datetime_boundaries();
         match self {
             BaseTimeRange::Day { .. } => {
@@ -124,12 +242,7 @@ impl BaseTimeRange {
                 BaseTimeRange::Day { year: new.year(), month: new.month() as u8, day: new.day() as u8 }
             }
             BaseTimeRange::Week { year, week } => {
-                //let new = first.checked_sub_days(Days::new(7)).unwrap();
-                let (mut year, mut week) = (year.clone(), week + 1);
-                if week > 52 {
-                    year += 1;
-                    week -= 52;
-                }
+                let (year, week) = next_week(*year, *week, *WEEK_BEGIN);
                 BaseTimeRange::Week { year, week }
             }
             BaseTimeRange::Month { .. } => {
@@ -146,7 +259,7 @@ impl BaseTimeRange {
         let dt = self.datetime_boundaries().0;
         match &self {
             BaseTimeRange::Day { year, month, day } => {  dt.format("%d. %B %Y").to_string() }
-            BaseTimeRange::Week { year, week } => { dt.format("Week %-V %Y").to_string() }
+            BaseTimeRange::Week { year, week } => { format!("Week {} {}", week, year) }
             BaseTimeRange::Month { year, month } => { dt.format("%B %Y").to_string() }
             BaseTimeRange::Year { year } => { dt.format("%Y").to_string() }
         }
@@ -170,11 +283,11 @@ impl TimeRange {
     }
 
     fn datetime_boundaries(&self) -> (DateTime<Tz>, DateTime<Tz>) {
-        // let min: DateTime<Tz> = DateTime::from_timestamp(i32::MIN as i64, 0).unwrap().with_timezone(&TIMEZONE);
-        // let max: DateTime<Tz> = DateTime::from_timestamp(i32::MAX as i64, 0).unwrap().with_timezone(&TIMEZONE);
-
-        let min: DateTime<Tz> = DateTime::from_timestamp(FIRST_STAMP, 0).unwrap().with_timezone(&TIMEZONE);
-        let max: DateTime<Tz> = Utc::now().with_timezone(&TIMEZONE).date_naive().and_hms_opt(0,0,0).unwrap().and_local_timezone(TIMEZONE).unwrap();
+        // all time is from the first to the last scrobble, without any it's just now
+        let now = Utc::now().timestamp();
+        let (first, last) = database::scrobble_span().unwrap_or((now, now));
+        let min: DateTime<Tz> = DateTime::from_timestamp(first, 0).unwrap_or_default().with_timezone(&*TIMEZONE);
+        let max: DateTime<Tz> = DateTime::from_timestamp(last, 0).unwrap_or_default().with_timezone(&*TIMEZONE);
 
         match self {
             TimeRange::Simple(base) => {
@@ -189,37 +302,105 @@ impl TimeRange {
             TimeRange::Infinite => {
                 (min,max)
             }
+            TimeRange::Rolling { amount, unit } => {
+                let now = Utc::now();
+                let start = match unit {
+                    RangeType::Day => now.checked_sub_signed(TimeDelta::days(*amount as i64)),
+                    RangeType::Week => now.checked_sub_signed(TimeDelta::weeks(*amount as i64)),
+                    RangeType::Month => now.checked_sub_months(Months::new(*amount)),
+                    RangeType::Year => amount.checked_mul(12).and_then(|months| now.checked_sub_months(Months::new(months))),
+                };
+                (
+                    start.map(|start| start.with_timezone(&*TIMEZONE)).unwrap_or(min),
+                    now.with_timezone(&*TIMEZONE),
+                )
+            }
+            TimeRange::Exact { start, end } => {
+                (
+                    start.and_then(|start| DateTime::from_timestamp(start, 0)).map(|start| start.with_timezone(&*TIMEZONE)).unwrap_or(min),
+                    end.and_then(|end| DateTime::from_timestamp(end, 0)).map(|end| end.with_timezone(&*TIMEZONE)).unwrap_or(max),
+                )
+            }
         }
     }
 
-    fn previous(&self) -> Option<Self> {
+    /// Exact range between the timestamps, if both of them are still a representable date
+    fn exact(start: i64, end: i64) -> Option<Self> {
+        DateTime::from_timestamp(start, 0)?;
+        DateTime::from_timestamp(end, 0)?;
+        Some(TimeRange::Exact { start: Some(start), end: Some(end) })
+    }
+
+    pub fn previous(&self) -> Option<Self> {
         match self {
             TimeRange::Simple(base) => {
                 Some(TimeRange::Simple(base.previous()))
             }
-            TimeRange::Composite { start, end } => {
-                todo!()
+            TimeRange::Composite { start: Some(start), end: Some(end) } => {
+                let (start, end) = common_units(start, end);
+                let length = start.distance(&end) + 1;
+                Some(TimeRange::Composite { start: Some(start.shift(-length)?), end: Some(end.shift(-length)?) })
+            }
+            TimeRange::Exact { start: Some(start), end: Some(end) } => {
+                let length = end.checked_sub(*start)?.checked_add(1)?;
+                TimeRange::exact(start.checked_sub(length)?, end.checked_sub(length)?)
             }
-            TimeRange::Infinite {} => {
+            TimeRange::Rolling { .. } => {
+                // the window right before, which doesn't move anymore
+                let (start, end) = self.timestamp_boundaries();
+                let length = end - start + 1;
+                TimeRange::exact(start.checked_sub(length)?, start - 1)
+            }
+            // there is nothing before or after ranges without an end
+            TimeRange::Composite { .. } | TimeRange::Exact { .. } | TimeRange::Infinite {} => {
                 None
             }
         }
     }
 
-    fn next(&self) -> Option<Self> {
+    pub fn next(&self) -> Option<Self> {
         match self {
             TimeRange::Simple(base) => {
                 Some(TimeRange::Simple(base.next()))
             }
-            TimeRange::Composite { start, end } => {
-                todo!()
+            TimeRange::Composite { start: Some(start), end: Some(end) } => {
+                let (start, end) = common_units(start, end);
+                let length = start.distance(&end) + 1;
+                Some(TimeRange::Composite { start: Some(start.shift(length)?), end: Some(end.shift(length)?) })
+            }
+            TimeRange::Exact { start: Some(start), end: Some(end) } => {
+                let length = end.checked_sub(*start)?.checked_add(1)?;
+                TimeRange::exact(start.checked_add(length)?, end.checked_add(length)?)
             }
-            TimeRange::Infinite {} => {
+            // rolling ranges end now
+            TimeRange::Composite { .. } | TimeRange::Exact { .. } | TimeRange::Infinite {} | TimeRange::Rolling { .. } => {
                 None
             }
         }
     }
 
+    /// Query parameters that describe this range in the API and on pages
+    pub fn to_query(&self) -> String {
+        match self {
+            TimeRange::Simple(base) => format!("during={}", base.describe_simple()),
+            TimeRange::Composite { start, end } => {
+                let start = start.as_ref().map(|start| format!("from={}", start.describe_simple()));
+                let end = end.as_ref().map(|end| format!("to={}", end.describe_simple()));
+                start.into_iter().chain(end).collect::<Vec<_>>().join("&")
+            }
+            TimeRange::Infinite => String::new(),
+            TimeRange::Rolling { .. } => format!("during={}", self.describe_simple()),
+            TimeRange::Exact { start, end } => {
+                // short timestamps would be read as years. In UTC, there is no + that would need to be escaped
+                let format = |timestamp: i64| DateTime::from_timestamp(timestamp, 0).unwrap_or_default()
+                    .to_rfc3339_opts(SecondsFormat::Secs, true);
+                let start = start.map(|start| format!("from={}", format(start)));
+                let end = end.map(|end| format!("to={}", format(end)));
+                start.into_iter().chain(end).collect::<Vec<_>>().join("&")
+            }
+        }
+    }
+
     pub fn includes(&self, timestamp: i64) -> bool {
         let (start, end) = self.timestamp_boundaries();
         (start <= timestamp) && (timestamp <= end)
@@ -229,24 +410,23 @@ impl TimeRange {
         // TODO: i don't really like this being done here
         match self {
             TimeRange::Simple(base) => true,
-            TimeRange::Composite { start, end } => {
+            TimeRange::Composite { start: Some(_), end: Some(_) } | TimeRange::Exact { start: Some(_), end: Some(_) } => {
                 let (s,e) = &self.timestamp_boundaries();
                 (s < e)
             }
-            TimeRange::Infinite {} => true,
+            // open ends depend on the scrobbles in the database, a range beyond them is just empty
+            TimeRange::Composite { .. } | TimeRange::Exact { .. } => true,
+            TimeRange::Infinite {} | TimeRange::Rolling { .. } => true,
         }
     }
 
     pub fn get_subranges(&self, subrange_type: RangeType) -> Vec<TimeRange> {
         let (first, last) = self.datetime_boundaries();
         let mut result = vec![];
-        let first_base_obj = match subrange_type {
-            RangeType::Day => BaseTimeRange::Day { year: first.year(), month: first.month() as u8, day: first.day() as u8 },
-            RangeType::Week => BaseTimeRange::Week { year: first.year(), week: first.iso_week().week() as u8 }, //TODO actual week logic
-            RangeType::Month => BaseTimeRange::Month { year: first.year(), month: first.month() as u8 },
-            RangeType::Year => BaseTimeRange::Year { year: first.year() },
-        };
-        let mut next = TimeRange::Simple(first_base_obj);
+        if first > last {
+            return result;
+        }
+        let mut next = TimeRange::Simple(BaseTimeRange::containing(first, subrange_type));
         loop {
             result.push(next.clone());
             if next.includes(last.timestamp()) {
@@ -270,6 +450,18 @@ impl TimeRange {
 
             }
             TimeRange::Infinite => { format!("{}", "All Time") }
+            TimeRange::Rolling { amount: 1, unit } => { format!("Last {}", unit) }
+            TimeRange::Rolling { amount, unit } => { format!("Last {} {}s", amount, unit) }
+            TimeRange::Exact { start, end } => {
+                let format = |timestamp: &i64| DateTime::from_timestamp(*timestamp, 0).unwrap_or_default()
+                    .with_timezone(&*TIMEZONE).format(&CONFIG.time_format).to_string();
+                match (start, end) {
+                    (Some(start), Some(end)) => { format!("{} to {}", format(start), format(end)) }
+                    (Some(start), None) => { format!("From {}", format(start)) }
+                    (None, Some(end)) => { format!("Until {}", format(end)) }
+                    (None, None) => { "All Time".to_string() }
+                }
+            }
         }
     }
     fn describe_simple(&self) -> String {
@@ -285,6 +477,18 @@ impl TimeRange {
 
             }
             TimeRange::Infinite => { format!("{}", "All Time") }
+            TimeRange::Rolling { amount, unit } => { format!("last{}{}s", amount, unit.to_string().to_lowercase()) }
+            TimeRange::Exact { start, end } => {
+                // the same format they can be given in
+                let format = |timestamp: &i64| DateTime::from_timestamp(*timestamp, 0).unwrap_or_default()
+                    .with_timezone(&*TIMEZONE).to_rfc3339();
+                match (start, end) {
+                    (Some(start), Some(end)) => { format!("{} - {}", format(start), format(end)) }
+                    (Some(start), None) => { format!("{} -", format(start)) }
+                    (None, Some(end)) => { format!("- {}", format(end)) }
+                    (None, None) => { "ALL".to_string() }
+                }
+            }
         }
     }
 }
@@ -5,7 +5,9 @@ use utoipa::IntoParams;
 use regex::Regex;
 use crate::database::errors::MalojaError;
 use crate::database::views::{Paginated, PaginationInfo};
-use crate::timeranges::{TimeRange, BaseTimeRange, ALL_TIME, RangeType};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use crate::configuration::{TIMEZONE, WEEK_BEGIN};
+use crate::timeranges::{TimeRange, BaseTimeRange, ALL_TIME, RangeType, weeks_in_year};
 
 // Query args
 #[derive(Deserialize, IntoParams, Debug)]
@@ -40,17 +42,41 @@ impl QueryPagination {
 #[derive(Deserialize, IntoParams, Debug)]
 #[into_params(parameter_in=Query)]
 pub struct QueryTimerange {
-    /// Show results starting at this time. Can be YYYY, YYYY/MM, YYYY/MM/DD or YYYYwWW (for week)
+    /// Show results starting at this time. Can be YYYY, YYYY/MM, YYYY/MM/DD, YYYYwWW (for week), `today`, `yesterday`,
+    /// `thisweek`, `lastweek`, `thismonth`, `lastmonth`, `thisyear`, `lastyear`, a UNIX timestamp or an ISO 8601 datetime
     #[param(example="2021/10")]
     from: Option<String>,
-    /// Show results ending at this time. Can be YYYY, YYYY/MM, YYYY/MM/DD or YYYYwWW (for week)
+    /// Show results ending at this time. Accepts the same values as from
     #[param(example="2021/12")]
     to: Option<String>,
-    /// Show results during this time range. Can be YYYY, YYYY/MM, YYYY/MM/DD or YYYYwWW (for week). Takes precedence over from and to
+    /// Show results during this time range. Can be YYYY, YYYY/MM, YYYY/MM/DD, YYYYwWW (for week), `today`, `yesterday`,
+    /// `thisweek`, `lastweek`, `thismonth`, `lastmonth`, `thisyear`, `lastyear`, or a rolling range up to now like
+    /// `last7days`, `last4weeks` or `last12months`. Takes precedence over from and to
     #[param(example="2024")]
     during: Option<String>,
 }
 
+/// One end of a range given by from or to
+enum TimeBound {
+    Range(BaseTimeRange),
+    Instant(i64),
+}
+
+impl TimeBound {
+    fn start(self) -> i64 {
+        match self {
+            TimeBound::Range(base) => TimeRange::Simple(base).timestamp_boundaries().0,
+            TimeBound::Instant(timestamp) => timestamp,
+        }
+    }
+    fn end(self) -> i64 {
+        match self {
+            TimeBound::Range(base) => TimeRange::Simple(base).timestamp_boundaries().1,
+            TimeBound::Instant(timestamp) => timestamp,
+        }
+    }
+}
+
 impl QueryTimerange {
     pub fn to_timerange(&self) -> Result<TimeRange, MalojaError> {
         if self.during.is_some() && (self.from.is_some() || self.to.is_some()) {
@@ -58,29 +84,90 @@ impl QueryTimerange {
                 message: "Can either specify a timerange with during, or start and end with from and to; not combine them".to_string(),
             })
         }
-        if self.during.is_none() && self.from.is_none() && self.to.is_none() {
-            return Ok(ALL_TIME);
-        }
         if let Some(during) = &self.during {
-            Ok(TimeRange::Simple(Self::match_string(during)?))
+            Self::match_during(during)
         }
         else {
-            let tr = TimeRange::Composite {
-                start: if let Some(from) = &self.from { Some(Self::match_string(from)?) } else { None },
-                end: if let Some(to) = &self.to { Some(Self::match_string(to)?) } else { None },
+            Self::match_bounds(self.from.as_deref(), self.to.as_deref())
+        }
+    }
+
+    /// A calendar unit or a rolling range
+    pub fn match_during(input: &str) -> Result<TimeRange, MalojaError> {
+        if let Some(caps) = Regex::new(r"^last(\d+)(day|week|month|year)s?$").unwrap().captures(input) {
+            let amount: u32 = caps[1].parse().map_err(|_| MalojaError::ParseError { message: "Amount is too large".to_string() })?;
+            let unit = match &caps[2] {
+                "day" => RangeType::Day,
+                "week" => RangeType::Week,
+                "month" => RangeType::Month,
+                _ => RangeType::Year,
             };
-            if tr.validate() {
-                Ok(tr)
-            }
-            else {
-                Err(MalojaError::ParseError {
-                    message: "From range must be before to range".to_string()
-                })
+            if amount == 0 {
+                return Err(MalojaError::ParseError { message: "Rolling range must cover at least one unit".to_string() });
             }
+            return Ok(TimeRange::Rolling { amount, unit });
         }
+        Ok(TimeRange::Simple(Self::match_string(input)?))
     }
 
-    fn match_string(input: &str) -> Result<BaseTimeRange, MalojaError> {
+    /// Range from the start of one time to the end of the other, either of which may be missing
+    pub fn match_bounds(from: Option<&str>, to: Option<&str>) -> Result<TimeRange, MalojaError> {
+        let (start, end) = (from.map(Self::match_bound).transpose()?, to.map(Self::match_bound).transpose()?);
+        let tr = match (start, end) {
+            (None, None) => return Ok(ALL_TIME),
+            (Some(TimeBound::Range(start)), Some(TimeBound::Range(end))) => TimeRange::Composite { start: Some(start), end: Some(end) },
+            (Some(TimeBound::Range(start)), None) => TimeRange::Composite { start: Some(start), end: None },
+            (None, Some(TimeBound::Range(end))) => TimeRange::Composite { start: None, end: Some(end) },
+            (start, end) => TimeRange::Exact { start: start.map(TimeBound::start), end: end.map(TimeBound::end) },
+        };
+        if tr.validate() {
+            Ok(tr)
+        }
+        else {
+            Err(MalojaError::ParseError {
+                message: "From range must be before to range".to_string()
+            })
+        }
+    }
+
+    fn match_bound(input: &str) -> Result<TimeBound, MalojaError> {
+        match Self::match_instant(input) {
+            Some(timestamp) => Ok(TimeBound::Instant(timestamp)),
+            None => Self::match_string(input).map(TimeBound::Range),
+        }
+    }
+
+    /// UNIX timestamps, and ISO 8601 datetimes with or without offset. Without offset, they are in the configured timezone
+    fn match_instant(input: &str) -> Option<i64> {
+        if Regex::new(r"^\d{5,}$").unwrap().is_match(input) {
+            // only timestamps that are an actual date
+            return input.parse().ok().filter(|timestamp| DateTime::from_timestamp(*timestamp, 0).is_some());
+        }
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(input) {
+            return Some(datetime.timestamp());
+        }
+        ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"].into_iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+            .and_then(|datetime| TIMEZONE.from_local_datetime(&datetime).earliest())
+            .map(|datetime| datetime.timestamp())
+    }
+
+    pub fn match_string(input: &str) -> Result<BaseTimeRange, MalojaError> {
+        let relative = match input {
+            "today" => Some((RangeType::Day, false)),
+            "yesterday" => Some((RangeType::Day, true)),
+            "thisweek" => Some((RangeType::Week, false)),
+            "lastweek" => Some((RangeType::Week, true)),
+            "thismonth" => Some((RangeType::Month, false)),
+            "lastmonth" => Some((RangeType::Month, true)),
+            "thisyear" => Some((RangeType::Year, false)),
+            "lastyear" => Some((RangeType::Year, true)),
+            _ => None,
+        };
+        if let Some((range_type, previous)) = relative {
+            let current = BaseTimeRange::containing(Utc::now().with_timezone(&*TIMEZONE), range_type);
+            return Ok(if previous { current.previous() } else { current });
+        }
         if let Some(caps) = Regex::new(r"^(\d{3,4})$").unwrap().captures(input) {
             return Ok(BaseTimeRange::Year { year: caps[1].parse().unwrap() });
         }
@@ -90,8 +177,13 @@ impl QueryTimerange {
         if let Some(caps) = Regex::new(r"^(\d{3,4})/(1[0-2]|0?[1-9])/(3[01]|[12][0-9]|0?[1-9])$").unwrap().captures(input) {
             return Ok(BaseTimeRange::Day { year: caps[1].parse().unwrap(), month: caps[2].parse().unwrap(), day: caps[3].parse().unwrap() });
         }
-        if let Some(caps) = Regex::new(r"^(\d{3,4})w(5[0-2]|[1-4][0-9]|[1-9])$").unwrap().captures(input) {
-            return Ok(BaseTimeRange::Week { year: caps[1].parse().unwrap(), week: caps[2].parse().unwrap() });
+        if let Some(caps) = Regex::new(r"^(\d{3,4})w(5[0-3]|[1-4][0-9]|0?[1-9])$").unwrap().captures(input) {
+            let (year, week): (i32, u8) = (caps[1].parse().unwrap(), caps[2].parse().unwrap());
+            let weeks = weeks_in_year(year, *WEEK_BEGIN);
+            if week > weeks {
+                return Err(MalojaError::ParseError { message: format!("{} only has {} weeks", year, weeks) });
+            }
+            return Ok(BaseTimeRange::Week { year, week });
         }
         Err(MalojaError::ParseError {
             message: "Timerange could not be parsed".to_string(),
@@ -165,4 +257,18 @@ impl QueryLimitTrack {
 #[into_params(parameter_in=Path)]
 pub struct PathEntity {
     pub id: u32
-}
\ No newline at end of file
+}
+#[derive(Deserialize, IntoParams, Debug)]
+#[into_params(parameter_in=Query)]
+pub struct QuerySubmission {
+    /// Reject the whole submission if any of the scrobbles already exists (same timestamp and track), instead of
+    /// silently skipping them
+    #[param(example=false)]
+    fail_on_existing: Option<bool>,
+}
+
+impl QuerySubmission {
+    pub fn fail_on_existing(&self) -> bool {
+        self.fail_on_existing.unwrap_or(false)
+    }
+}